@@ -1,26 +1,39 @@
 import { Hono } from "hono";
-import { type BuildConfig, config_defaults } from "./src/config.ts";
+import {
+    type BuildConfig,
+    config_defaults,
+    graphServerUrl,
+} from "./src/config.ts";
 import { join, normalize } from "@std/path/posix";
 import { toFileUrl } from "@std/path/to-file-url";
-import type { NPMPackage } from "./src/npm/package.ts";
+import { fetchNpmDir, type NPMPackage } from "./src/npm/package.ts";
 import { format, parse } from "@std/semver";
 import { NPMCompiler } from "./src/npm/compiler.ts";
 import { assert } from "node:console";
-import { deno_info } from "./src/deno/info.ts";
 import { serveFile } from "@std/http/file-server";
-import { run_graph_server } from "./src/graph/server.ts";
+import {
+    fetchGraphApi,
+    type GraphApi,
+    run_graph_server,
+} from "./src/graph/server.ts";
+import { hmr_routes } from "./src/hmr/mod.ts";
 
 export function dev_server(config_options: BuildConfig): Hono {
     const config = config_defaults(config_options);
 
-    if (config.run_graph_server) {
-        run_graph_server(config);
-    }
+    const graph_api: Promise<GraphApi> = config.run_graph_server
+        ? run_graph_server(config).then((server) => server.ready)
+        : Promise.resolve({ url: graphServerUrl(config) });
+    graph_api.catch((e) => {
+        config.logger.error`${e}`;
+    });
 
-    const npm_compiler = new NPMCompiler(config);
+    const npm_compiler = new NPMCompiler(config, graph_api);
 
     const app = new Hono();
 
+    hmr_routes(app, config);
+
     app.get("/@module/error/:id", (c) => {
         return new Response(
             `throw new Error(${
@@ -138,6 +151,10 @@ export function dev_server(config_options: BuildConfig): Hono {
         }
     });
 
+    let npm_dir: Promise<string> | null = null;
+
+    // served as extracted by deno, only the package metadata is checked
+    // against the lockfile (`/api/v1/npm/metadata`)
     app.get("/@npm-data/*", async (c) => {
         const url = new URL(c.req.url);
 
@@ -145,11 +162,56 @@ export function dev_server(config_options: BuildConfig): Hono {
 
         assert(!path.includes(".."), "Path traversal attempt detected");
 
-        const real_path = join((await deno_info).npmCache, path);
+        npm_dir ??= graph_api.then(fetchNpmDir);
+        const real_path = join(await npm_dir, path);
 
         return serveFile(c.req.raw, real_path);
     });
 
+    app.get("/@jsr/*", async (c) => {
+        const url = new URL(c.req.url);
+        const path = url.pathname.split("/@jsr/")[1];
+
+        return await serveModule(new URL(`https://jsr.io/${path}`)) ??
+            c.text("Module not found", 404);
+    });
+
+    let workspace: Promise<Map<string, string>> | null = null;
+
+    async function fetchWorkspace() {
+        const res = await fetchGraphApi(await graph_api, "/api/v1/workspace");
+        const members: { name: string; path: string }[] = res.ok
+            ? (await res.json()).members
+            : [];
+        return new Map(members.map((m) => [m.name, m.path]));
+    }
+
+    app.get("/@workspace/:member/*", async (c, next) => {
+        workspace ??= fetchWorkspace();
+        const member_path = (await workspace).get(
+            decodeURIComponent(c.req.param("member")),
+        );
+
+        if (!member_path) {
+            return c.text("Workspace member not found", 404);
+        }
+
+        const url = new URL(c.req.url);
+        const path = normalize(
+            url.pathname.split("/").slice(3).join("/"),
+        );
+
+        if (path.startsWith(".")) {
+            config.logger
+                .warn`Path traversal attempt detected: requested='${url.pathname}' normalized='${path}'`;
+            return c.text("Access denied", 403);
+        }
+
+        const module_id = toFileUrl(join(member_path, path));
+
+        return (await serveModule(module_id)) ?? await next();
+    });
+
     app.get("/*", async (c, next) => {
         const url = new URL(c.req.url);
         const normalizedPath = normalize(url.pathname);
@@ -160,6 +222,20 @@ export function dev_server(config_options: BuildConfig): Hono {
             return c.text("Access denied", 403);
         }
 
+        if (
+            normalizedPath.endsWith("/") || normalizedPath.endsWith(".html")
+        ) {
+            const page = toFileUrl(
+                join(
+                    config.dev_server_root,
+                    normalizedPath.endsWith("/")
+                        ? join(normalizedPath, "index.html")
+                        : normalizedPath,
+                ),
+            );
+            return (await servePage(page)) ?? await next();
+        }
+
         const module_id = toFileUrl(
             join(config.dev_server_root, normalizedPath),
         );
@@ -167,21 +243,83 @@ export function dev_server(config_options: BuildConfig): Hono {
         return (await serveModule(module_id)) ?? await next();
     });
 
-    async function serveModule(specifier: URL) {
-        const api_url = new URL("http://[::1]:3000/api/v1/transform/module");
-
-        api_url.searchParams.set("module", specifier.href);
-
-        const res = await fetch(api_url);
+    /**
+     * HTML page with rewritten module scripts and the HMR client
+     */
+    async function servePage(page: URL) {
+        const res = await fetchGraphApi(await graph_api, "/api/v1/html", {
+            page: page.href,
+            dev: "true",
+        });
 
         if (res.ok) {
-            return new Response((await res.json()).result.code, {
+            return new Response(res.body, {
                 headers: {
-                    "Content-Type": "application/javascript",
+                    "Content-Type": "text/html;charset=UTF-8",
                     "Cache-Control": "no-store",
                 },
             });
+        } else if (res.status === 404) {
+            await res.body?.cancel();
+            return null;
+        } else {
+            const { error, description } = await res.json();
+            config.logger.error`${error}: ${description}`;
+            return null;
+        }
+    }
+
+    /**
+     * Serves the transformed module, or its source map if the URL ends with `.map`
+     * (`source_maps: "external"`)
+     */
+    async function serveModule(specifier: URL) {
+        const source_map = specifier.href.endsWith(".map");
+        const module = source_map
+            ? new URL(specifier.href.slice(0, -".map".length))
+            : specifier;
+
+        const res = await fetchGraphApi(
+            await graph_api,
+            "/api/v1/transform/module",
+            { module: module.href, hmr: String(config.hmr) },
+        );
+
+        if (res.ok && source_map) {
+            const { result } = await res.json();
+            return result.source_map
+                ? new Response(result.source_map, {
+                    headers: {
+                        "Content-Type": "application/json",
+                        "Cache-Control": "no-store",
+                    },
+                })
+                : null;
+        } else if (res.ok) {
+            const headers: Record<string, string> = {
+                "Content-Type": "application/javascript",
+                "Cache-Control": "no-store",
+            };
+            // modulepreload hints for the transitive imports
+            const link = res.headers.get("Link");
+            if (link) {
+                headers["Link"] = link;
+            }
+            return new Response((await res.json()).result.code, { headers });
+        } else if (res.status === 409) {
+            const { error, description } = await res.json();
+            config.logger.error`${error}: ${description}`;
+            return new Response(
+                `throw new Error(${JSON.stringify(`${error}: ${description}`)});`,
+                {
+                    headers: {
+                        "Content-Type": "application/javascript;charset=UTF-8",
+                        "Cache-Control": "no-store",
+                    },
+                },
+            );
         } else {
+            await res.body?.cancel();
             return null;
         }
     }
@@ -0,0 +1,534 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashSet, VecDeque},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use brotli::enc::BrotliEncoderParams;
+use flate2::{Compression, write::GzEncoder};
+use serde::Serialize;
+
+use crate::{
+    config::{SourceMapMode, SourceMapOptions, Target},
+    graph::{GraphModule, ModuleGraph, NPMPackage},
+    specifier::ModuleSpecifier,
+    transpiler::{
+        TransformPool,
+        transform::{ModuleUrls, TransformOptions, collect_imports},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Sizes {
+    pub raw: u64,
+    /// `None` for modules that are not transformed by the graph server (npm)
+    pub transformed: Option<u64>,
+    pub gzip: u64,
+    pub brotli: u64,
+}
+
+impl Sizes {
+    fn of(raw: &[u8], output: &[u8], transformed: bool) -> Self {
+        Sizes {
+            raw: raw.len() as u64,
+            transformed: transformed.then_some(output.len() as u64),
+            gzip: gzip_size(output),
+            brotli: brotli_size(output),
+        }
+    }
+
+    fn add(&mut self, other: &Sizes) {
+        self.raw += other.raw;
+        self.transformed = match (self.transformed, other.transformed) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.gzip += other.gzip;
+        self.brotli += other.brotli;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageKind {
+    Npm,
+    Jsr,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModuleSize {
+    pub specifier: ModuleSpecifier,
+    /// Path relative to the project root for local modules
+    pub label: String,
+    /// Package id for jsr modules
+    pub package: Option<String>,
+    pub sizes: Sizes,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageSize {
+    pub kind: PackageKind,
+    pub id: String,
+    pub modules: usize,
+    pub sizes: Sizes,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SizeReport {
+    pub total: Sizes,
+    pub modules: Vec<ModuleSize>,
+    pub packages: Vec<PackageSize>,
+    /// Modules that could not be loaded or transformed
+    pub errors: Vec<String>,
+}
+
+/// Transforms every ESM module reachable from the root and measures its size.
+///
+/// npm packages are not transformed by the graph server, their size is the sum of the files
+/// their imported exports resolve to in the deno npm cache (if `npm_cache` is known), including
+/// transitive dependencies. Modules are lowered to `target` like in the build.
+pub async fn analyze_sizes(
+    graph: Arc<ModuleGraph>,
+    npm_cache: Option<PathBuf>,
+    target: &Target,
+    pool: &TransformPool,
+) -> SizeReport {
+    let mut report = SizeReport::default();
+    let mut tasks = Vec::new();
+    let mut npm_imports: Vec<(Arc<NPMPackage>, String)> = Vec::new();
+
+    for module in graph.walk(None) {
+        match module {
+            GraphModule::Esm(module) => match module.load_code().await {
+                Ok(code) => {
+                    let task = pool.transform(TransformOptions {
+                        code: code.clone(),
+                        hmr: false,
+                        graph: graph.clone(),
+                        module: module.clone(),
+                        // the map is not part of the payload we want to measure
+                        source_map: SourceMapOptions {
+                            mode: SourceMapMode::None,
+                            ..Default::default()
+                        },
+                        target: target.clone(),
+                        urls: ModuleUrls::DevServer,
+                    });
+                    tasks.push((module, code, task));
+                }
+                Err(err) => {
+                    report
+                        .errors
+                        .push(format!("Failed to read {}: {}", module.specifier(), err))
+                }
+            },
+            GraphModule::Npm(module) => npm_imports.push((module.package(), module.subpath())),
+            GraphModule::Virtual(_) => {}
+        }
+    }
+
+    let mut packages: BTreeMap<(PackageKind, String), PackageSize> = BTreeMap::new();
+
+    for (module, code, task) in tasks {
+        let Some(result) = task.result().await else {
+            report
+                .errors
+                .push(format!("Failed to transform {}", module.specifier()));
+            continue;
+        };
+
+        let specifier = module.specifier();
+
+        let sizes = tokio::task::spawn_blocking(move || {
+            Sizes::of(code.as_bytes(), result.code.as_bytes(), true)
+        })
+        .await
+        .unwrap();
+
+        let package = graph
+            .jsr_package_of(&specifier)
+            .map(|package| package.id().to_string());
+
+        if let Some(package) = &package {
+            let entry = packages
+                .entry((PackageKind::Jsr, package.clone()))
+                .or_insert_with(|| PackageSize {
+                    kind: PackageKind::Jsr,
+                    id: package.clone(),
+                    modules: 0,
+                    sizes: Sizes::default(),
+                });
+            entry.modules += 1;
+            entry.sizes.add(&sizes);
+        }
+
+        report.total.add(&sizes);
+        report.modules.push(ModuleSize {
+            label: specifier
+                .to_file_path()
+                .ok()
+                .and_then(|path| {
+                    path.strip_prefix(&graph.root_dir)
+                        .ok()
+                        .map(|p| p.to_string_lossy().replace("\\", "/"))
+                })
+                .unwrap_or_else(|| specifier.to_string()),
+            specifier: specifier.as_ref().clone(),
+            package,
+            sizes,
+        });
+    }
+
+    if let Some(npm_cache) = npm_cache {
+        let npm_graph = graph.clone();
+        let (npm_packages, errors) = tokio::task::spawn_blocking(move || {
+            npm_package_sizes(&npm_graph, &npm_cache, npm_imports)
+        })
+        .await
+        .unwrap();
+
+        for package in npm_packages {
+            report.total.add(&package.sizes);
+            packages.insert((PackageKind::Npm, package.id.clone()), package);
+        }
+        report.errors.extend(errors);
+    }
+
+    report.modules.sort_by_key(|m| Reverse(m.sizes.brotli));
+
+    report.packages = packages.into_values().collect();
+    report.packages.sort_by_key(|p| Reverse(p.sizes.brotli));
+
+    report
+}
+
+impl SizeReport {
+    /// Self-contained HTML page with a treemap of the report
+    pub fn to_html(&self) -> String {
+        let json = serde_json::to_string(self).unwrap().replace("</", "<\\/");
+        include_str!("treemap.html").replace("/*REPORT*/null", &json)
+    }
+}
+
+/// Sizes of the npm packages reachable from `imports` (package, export subpath). Only the files the
+/// exports resolve to and their relative imports are counted, bare imports are followed into the
+/// package dependencies.
+fn npm_package_sizes(
+    graph: &ModuleGraph,
+    npm_cache: &Path,
+    imports: Vec<(Arc<NPMPackage>, String)>,
+) -> (Vec<PackageSize>, Vec<String>) {
+    let mut packages: BTreeMap<String, PackageSize> = BTreeMap::new();
+    let mut errors = Vec::new();
+    let mut seen_imports = HashSet::new();
+    let mut seen_files = HashSet::new();
+    let mut queue = VecDeque::from(imports);
+
+    while let Some((package, subpath)) = queue.pop_front() {
+        let id = package.id().to_string();
+        if !seen_imports.insert((id.clone(), subpath.clone())) {
+            continue;
+        }
+
+        let entry = match resolve_npm_export(&package.local_dir(npm_cache), &subpath) {
+            Ok(entry) => entry,
+            Err(err) => {
+                errors.push(format!("Failed to resolve npm:{}/{}: {}", id, subpath, err));
+                continue;
+            }
+        };
+
+        let size = packages.entry(id.clone()).or_insert_with(|| PackageSize {
+            kind: PackageKind::Npm,
+            id,
+            modules: 0,
+            sizes: Sizes::default(),
+        });
+
+        let mut files = vec![entry];
+        while let Some(file) = files.pop() {
+            if !seen_files.insert(file.clone()) {
+                continue;
+            }
+
+            let content = match std::fs::read_to_string(&file) {
+                Ok(content) => content,
+                Err(err) => {
+                    errors.push(format!("Failed to read {}: {}", file.display(), err));
+                    continue;
+                }
+            };
+
+            size.modules += 1;
+            size.sizes
+                .add(&Sizes::of(content.as_bytes(), content.as_bytes(), false));
+
+            if file.extension().is_some_and(|ext| ext == "json") {
+                continue;
+            }
+
+            for import in npm_file_imports(&content) {
+                if import.starts_with('.') {
+                    let path = file.parent().unwrap().join(&import);
+                    match resolve_npm_file(&path) {
+                        Some(path) => files.push(path),
+                        None => errors.push(format!(
+                            "Failed to resolve {} from {}",
+                            import,
+                            file.display()
+                        )),
+                    }
+                } else {
+                    // builtins and optional dependencies that are not installed are skipped
+                    let (name, subpath) = split_package_name(&import);
+                    if let Some(dependency) = package
+                        .dependencies()
+                        .into_iter()
+                        .find(|dependency| dependency.name == name)
+                        .and_then(|dependency| graph.get_npm_package(&dependency.to_string()))
+                    {
+                        queue.push_back((dependency, subpath.to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    (packages.into_values().collect(), errors)
+}
+
+/// Conditions of the `exports` field that apply to the browser build, in order of preference
+const EXPORT_CONDITIONS: [&str; 5] = ["browser", "import", "module", "default", "require"];
+
+/// File `subpath` (`""` for the package root) of the package in `dir` resolves to
+fn resolve_npm_export(dir: &Path, subpath: &str) -> Result<PathBuf, String> {
+    let manifest = std::fs::read_to_string(dir.join("package.json"))
+        .map_err(|err| format!("Failed to read package.json: {}", err))?;
+    let manifest: serde_json::Value = serde_json::from_str(&manifest)
+        .map_err(|err| format!("Failed to parse package.json: {}", err))?;
+
+    let key = if subpath.is_empty() {
+        ".".to_string()
+    } else {
+        format!("./{}", subpath)
+    };
+
+    let target = match manifest.get("exports") {
+        Some(exports) => {
+            resolve_exports(exports, &key).ok_or_else(|| format!("{} is not exported", key))?
+        }
+        None if subpath.is_empty() => ["browser", "module", "main"]
+            .iter()
+            .find_map(|field| manifest.get(*field).and_then(|value| value.as_str()))
+            .unwrap_or("index.js")
+            .to_string(),
+        None => key,
+    };
+
+    resolve_npm_file(&dir.join(&target)).ok_or_else(|| format!("{} does not exist", target))
+}
+
+fn resolve_exports(exports: &serde_json::Value, key: &str) -> Option<String> {
+    match exports {
+        serde_json::Value::Object(map) if map.keys().any(|k| k.starts_with('.')) => {
+            if let Some(target) = map.get(key) {
+                return resolve_conditions(target);
+            }
+
+            // subpath patterns like `"./*": "./dist/*.js"`
+            map.iter().find_map(|(pattern, target)| {
+                let (prefix, suffix) = pattern.split_once('*')?;
+                let matched = key.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                Some(resolve_conditions(target)?.replace('*', matched))
+            })
+        }
+        _ if key == "." => resolve_conditions(exports),
+        _ => None,
+    }
+}
+
+fn resolve_conditions(target: &serde_json::Value) -> Option<String> {
+    match target {
+        serde_json::Value::String(target) => Some(target.clone()),
+        serde_json::Value::Array(targets) => targets.iter().find_map(resolve_conditions),
+        serde_json::Value::Object(map) => EXPORT_CONDITIONS
+            .iter()
+            .find_map(|condition| map.get(*condition).and_then(resolve_conditions)),
+        _ => None,
+    }
+}
+
+/// Applies the node extension and directory index probing
+fn resolve_npm_file(path: &Path) -> Option<PathBuf> {
+    let candidates = [
+        path.to_path_buf(),
+        path.with_added_extension("js"),
+        path.with_added_extension("mjs"),
+        path.with_added_extension("cjs"),
+        path.with_added_extension("json"),
+        path.join("index.js"),
+    ];
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .and_then(|candidate| candidate.canonicalize().ok())
+}
+
+/// Static imports and `require()` calls of an npm module
+fn npm_file_imports(code: &str) -> Vec<String> {
+    let mut imports = collect_imports(code).unwrap_or_default();
+
+    let mut rest = code;
+    while let Some(pos) = rest.find("require(") {
+        rest = &rest[pos + "require(".len()..];
+        let Some(quote) = rest.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            continue;
+        };
+        if let Some(end) = rest[1..].find(quote) {
+            imports.push(rest[1..end + 1].to_string());
+        }
+    }
+
+    imports
+}
+
+/// `@scope/name/sub/path` => (`@scope/name`, `sub/path`)
+fn split_package_name(import: &str) -> (&str, &str) {
+    let separators = if import.starts_with('@') { 2 } else { 1 };
+    let end = import
+        .match_indices('/')
+        .nth(separators - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(import.len());
+    (&import[..end], import[end..].trim_start_matches('/'))
+}
+
+fn gzip_size(data: &[u8]) -> u64 {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap().len() as u64
+}
+
+fn brotli_size(data: &[u8]) -> u64 {
+    let mut output = Vec::new();
+    brotli::BrotliCompress(&mut &data[..], &mut output, &BrotliEncoderParams::default()).unwrap();
+    output.len() as u64
+}
+
+#[tokio::test]
+async fn test_analyze_sizes() {
+    use crate::deno::info::DenoInfo;
+
+    let dir = std::env::temp_dir().join(format!("deno-plc-build-size-{}", std::process::id()));
+    let write = |path: &str, content: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+
+    write(
+        "app/main.ts",
+        "import { a } from \"npm:dep@1.0.0\";\nexport const x: number = a;\n",
+    );
+    write(
+        "npm/registry.npmjs.org/dep/1.0.0/package.json",
+        r#"{ "exports": { ".": { "types": "./index.d.ts", "import": "./esm/index.js" }, "./unused": "./unused.js" } }"#,
+    );
+    write(
+        "npm/registry.npmjs.org/dep/1.0.0/esm/index.js",
+        "export { b as a } from \"./util\";\nimport \"inner/feature\";\n",
+    );
+    write(
+        "npm/registry.npmjs.org/dep/1.0.0/esm/util.js",
+        "export const b = 1;\n",
+    );
+    write(
+        "npm/registry.npmjs.org/dep/1.0.0/unused.js",
+        &"x".repeat(10000),
+    );
+    write(
+        "npm/registry.npmjs.org/inner/2.0.0/package.json",
+        r#"{ "main": "lib/index.js" }"#,
+    );
+    write(
+        "npm/registry.npmjs.org/inner/2.0.0/feature.js",
+        "module.exports = require('./lib/helper');\n",
+    );
+    write(
+        "npm/registry.npmjs.org/inner/2.0.0/lib/helper.js",
+        "module.exports = 1;\n",
+    );
+    write(
+        "npm/registry.npmjs.org/inner/2.0.0/lib/index.js",
+        &"x".repeat(10000),
+    );
+
+    let main = ModuleSpecifier::from_file_path(dir.join("app/main.ts")).unwrap();
+    let info: DenoInfo = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "roots": [main],
+        "modules": [
+            {
+                "kind": "esm",
+                "specifier": main,
+                "local": dir.join("app/main.ts"),
+                "mediaType": "TypeScript",
+                "dependencies": [
+                    { "specifier": "npm:dep@1.0.0", "code": { "specifier": "npm:/dep@1.0.0" } }
+                ]
+            },
+            { "kind": "npm", "specifier": "npm:/dep@1.0.0", "npmPackage": "dep@1.0.0" }
+        ],
+        "npmPackages": {
+            "dep@1.0.0": {
+                "name": "dep",
+                "version": "1.0.0",
+                "dependencies": ["inner@2.0.0"],
+                "registryUrl": "https://registry.npmjs.org/"
+            },
+            "inner@2.0.0": {
+                "name": "inner",
+                "version": "2.0.0",
+                "dependencies": [],
+                "registryUrl": "https://registry.npmjs.org/"
+            }
+        },
+        "packages": {},
+        "redirects": {}
+    }))
+    .unwrap();
+
+    let mut graph = ModuleGraph::new();
+    graph
+        .build(
+            info,
+            &BTreeMap::from([("main".to_string(), main)]),
+            dir.join("app"),
+        )
+        .await;
+
+    let report = analyze_sizes(
+        Arc::new(graph),
+        Some(dir.join("npm")),
+        &Target::default(),
+        TransformPool::get(),
+    )
+    .await;
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(report.errors, Vec::<String>::new());
+    assert_eq!(report.modules.len(), 1);
+    assert_eq!(report.modules[0].label, "main.ts");
+    assert!(report.modules[0].sizes.transformed.is_some());
+
+    let package = |id: &str| report.packages.iter().find(|p| p.id == id).unwrap();
+    assert_eq!(package("dep@1.0.0").modules, 2);
+    assert_eq!(package("dep@1.0.0").sizes.raw, 77);
+    assert_eq!(package("inner@2.0.0").modules, 2);
+    assert_eq!(package("inner@2.0.0").sizes.raw, 62);
+    assert_eq!(report.total.raw, report.modules[0].sizes.raw + 77 + 62);
+}
@@ -0,0 +1,299 @@
+//! Transformed modules, reused as long as the source of the module is unchanged.
+//! The cache is bounded, the least recently used modules are evicted.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+
+use crate::{
+    config::Config, metrics::metrics, specifier::ModuleSpecifier,
+    transpiler::transform::TransformResult,
+};
+
+/// Megabytes of transformed code and source maps
+pub const DEFAULT_CACHE_SIZE: usize = 256;
+
+/// Transforms in progress by module, HMR flag and whether they run in the background
+type InFlight = HashMap<(ModuleSpecifier, bool, bool), Arc<OnceCell<TransformResult>>>;
+
+/// Entries are keyed by module and HMR flag and validated with the sha256 of the source.
+/// The cache is cleared whenever the graph is swapped, the resolution of the imports may have changed.
+#[derive(Debug, Clone)]
+pub struct TransformCache {
+    entries: Arc<Mutex<Entries>>,
+    /// Bytes of code and source maps kept
+    capacity: usize,
+    /// Transforms in progress, waiting requests share their result
+    in_flight: Arc<Mutex<InFlight>>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    map: HashMap<(ModuleSpecifier, bool), CacheEntry>,
+    bytes: usize,
+    /// Incremented on every access, orders the entries by their last use
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    sha256: String,
+    result: TransformResult,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn size(&self) -> usize {
+        self.result.code.len() + self.result.source_map.as_ref().map_or(0, String::len)
+    }
+}
+
+impl Default for TransformCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_SIZE * 1024 * 1024)
+    }
+}
+
+impl TransformCache {
+    /// Keeps up to `capacity` bytes of transformed code and source maps
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Default::default(),
+            capacity,
+            in_flight: Default::default(),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.transform_cache_size.unwrap_or(DEFAULT_CACHE_SIZE) * 1024 * 1024)
+    }
+
+    pub fn get(
+        &self,
+        specifier: &ModuleSpecifier,
+        hmr: bool,
+        sha256: &str,
+    ) -> Option<TransformResult> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let result = entries
+            .map
+            .get_mut(&(specifier.clone(), hmr))
+            .filter(|entry| entry.sha256 == sha256)
+            .map(|entry| {
+                entry.last_used = clock;
+                entry.result.clone()
+            });
+
+        match result {
+            Some(_) => metrics().transform_cache_hits.inc(),
+            None => metrics().transform_cache_misses.inc(),
+        }
+
+        result
+    }
+
+    pub fn insert(
+        &self,
+        specifier: &ModuleSpecifier,
+        hmr: bool,
+        sha256: String,
+        result: &TransformResult,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let entry = CacheEntry {
+            sha256,
+            result: result.clone(),
+            last_used: entries.clock,
+        };
+
+        let size = entry.size();
+        if let Some(old) = entries.map.remove(&(specifier.clone(), hmr)) {
+            entries.bytes -= old.size();
+        }
+        if size > self.capacity {
+            return;
+        }
+
+        entries.bytes += size;
+        entries.map.insert((specifier.clone(), hmr), entry);
+
+        if entries.bytes > self.capacity {
+            entries.evict(self.capacity / 4 * 3);
+        }
+    }
+
+    /// Runs `transform` unless the same module is already being transformed, in which case its result is
+    /// awaited. If the running transform fails or is cancelled, one of the waiting requests takes over.
+    /// `background` transforms (warm-up) have their own flights, requests never wait behind their priority.
+    pub async fn single_flight<F, Fut, E>(
+        &self,
+        specifier: &ModuleSpecifier,
+        hmr: bool,
+        background: bool,
+        transform: F,
+    ) -> Result<TransformResult, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<TransformResult, E>>,
+    {
+        let key = (specifier.clone(), hmr, background);
+        let flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let result = flight.get_or_try_init(transform).await.cloned();
+
+        // later requests must read the source again
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| Arc::ptr_eq(current, &flight))
+        {
+            in_flight.remove(&key);
+        }
+
+        result
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.map.clear();
+        entries.bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Size of the cached code and source maps
+    pub fn bytes(&self) -> usize {
+        self.entries.lock().unwrap().bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Entries {
+    /// Removes the least recently used entries until at most `target` bytes are left.
+    /// Evicting down to less than the capacity avoids sorting the entries on every insert.
+    fn evict(&mut self, target: usize) {
+        let mut by_use: Vec<_> = self
+            .map
+            .iter()
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        by_use.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        for (_, key) in by_use {
+            if self.bytes <= target {
+                break;
+            }
+            if let Some(entry) = self.map.remove(&key) {
+                self.bytes -= entry.size();
+                metrics().transform_cache_evictions.inc();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+    let cache = TransformCache::new(100);
+    let result = TransformResult {
+        code: "x".repeat(30),
+        source_map: None,
+    };
+    let specifier = |name: &str| ModuleSpecifier::parse(&format!("file:///{}.ts", name)).unwrap();
+
+    for name in ["a", "b", "c"] {
+        cache.insert(&specifier(name), false, name.to_string(), &result);
+    }
+    assert!(cache.get(&specifier("a"), false, "a").is_some());
+
+    // over capacity, evicts b and c (least recently used) down to 3/4 of the capacity
+    cache.insert(&specifier("d"), false, "d".to_string(), &result);
+    assert!(cache.get(&specifier("b"), false, "b").is_none());
+    assert!(cache.get(&specifier("c"), false, "c").is_none());
+    assert!(cache.get(&specifier("a"), false, "a").is_some());
+    assert!(cache.get(&specifier("d"), false, "d").is_some());
+    assert_eq!(cache.bytes(), 60);
+
+    // larger than the whole cache, replaces the old entry without being cached
+    let large = TransformResult {
+        code: "x".repeat(200),
+        source_map: None,
+    };
+    cache.insert(&specifier("a"), false, "a2".to_string(), &large);
+    assert!(cache.get(&specifier("a"), false, "a2").is_none());
+    assert_eq!(cache.bytes(), 30);
+}
+
+#[tokio::test]
+async fn test_single_flight() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let cache = TransformCache::default();
+    let specifier = ModuleSpecifier::parse("file:///main.ts").unwrap();
+    let runs = AtomicUsize::new(0);
+
+    let transform = || async {
+        runs.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        Ok::<_, ()>(TransformResult {
+            code: "export {};".to_string(),
+            source_map: None,
+        })
+    };
+
+    let (a, b) = tokio::join!(
+        cache.single_flight(&specifier, false, false, transform),
+        cache.single_flight(&specifier, false, false, transform),
+    );
+
+    assert_eq!(a.unwrap().code, b.unwrap().code);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert!(cache.in_flight.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_single_flight_skips_background() {
+    let cache = TransformCache::default();
+    let specifier = ModuleSpecifier::parse("file:///main.ts").unwrap();
+    let result = TransformResult {
+        code: "export {};".to_string(),
+        source_map: None,
+    };
+
+    // a warm-up transform that is still queued behind the requests
+    let warm_up = cache.single_flight(&specifier, false, true, || async {
+        std::future::pending::<Result<TransformResult, ()>>().await
+    });
+    let request = cache.single_flight(&specifier, false, false, || async {
+        Ok::<_, ()>(result.clone())
+    });
+
+    let race = async {
+        tokio::select! {
+            biased;
+            _ = warm_up => panic!("the warm-up never finishes"),
+            request = request => request.unwrap(),
+        }
+    };
+
+    let request = tokio::time::timeout(std::time::Duration::from_secs(1), race)
+        .await
+        .expect("the request waited for the warm-up");
+    assert_eq!(request.code, result.code);
+}
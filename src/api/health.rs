@@ -0,0 +1,61 @@
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Serialize;
+
+use super::{AppState, WarmUpProgress};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum GraphStatus {
+    Building,
+    Ready,
+    Failed {
+        error: String,
+    },
+    /// Shutting down, requests in flight are finished
+    Draining,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct HealthResponse {
+    status: &'static str,
+    version: &'static str,
+}
+
+/// The process is alive, independent of the graph
+pub(super) async fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ReadyResponse {
+    #[serde(flatten)]
+    status: GraphStatus,
+    modules: usize,
+    entrypoints: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warm_up: Option<WarmUpProgress>,
+}
+
+/// 200 once the graph is built, 503 while building, after a failed build and while draining
+pub(super) async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadyResponse>) {
+    let status = state.status().as_ref().clone();
+    let graph = state.graph();
+
+    let code = match status {
+        GraphStatus::Ready => StatusCode::OK,
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (
+        code,
+        Json(ReadyResponse {
+            status,
+            modules: graph.modules().count(),
+            entrypoints: graph.entrypoints().len(),
+            warm_up: state.warm_up_progress(),
+        }),
+    )
+}
@@ -1,33 +1,322 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, OnceLock},
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use crate::{
+    config::{SourceMapOptions, Target},
+    deno::{
+        lockfile::{IntegrityError, sha256_hex},
+        workspace::Workspace,
+    },
+    graph::{
+        cycles::ImportCycle,
+        export::{ExportOptions, ModuleScheme},
+    },
+    html::HtmlPage,
+    jsr::id::JsrPackageId,
+    metrics::{Gauges, metrics},
     npm::id::NPMPackageId,
     specifier::ModuleSpecifier,
     transpiler::{
-        TransformPool,
-        transform::{TransformOptions, TransformResult},
+        Priority, TransformPool,
+        transform::{ModuleUrls, TransformOptions, TransformResult, module_url},
     },
 };
-use axum::{Json, Router, extract::Query, http::StatusCode, routing::get};
+use arc_swap::ArcSwap;
+use axum::{
+    Json, Router,
+    extract::{MatchedPath, Query, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use path_clean::PathClean;
 use serde::{Deserialize, Serialize};
+use tracing::{Instrument, debug, debug_span, instrument};
 use url::Url;
 
 use crate::graph::ModuleGraph;
 
-static GRAPH: OnceLock<Arc<ModuleGraph>> = OnceLock::new();
+mod cache;
+mod health;
+mod warm_up;
+
+pub use cache::TransformCache;
+pub use health::GraphStatus;
+pub use warm_up::{WarmUpProgress, WarmUpStatus};
+
+/// Shared state of the API routes. The graph can be swapped while the server is running.
+#[derive(Clone)]
+pub struct AppState {
+    pub graph: Arc<ArcSwap<ModuleGraph>>,
+    pub status: Arc<ArcSwap<GraphStatus>>,
+    pub transpiler: TransformPool,
+    pub transforms: TransformCache,
+    pub source_maps: SourceMapOptions,
+    /// Syntax the transformed modules are lowered to
+    pub target: Target,
+    warm_up: Arc<Mutex<Option<WarmUpProgress>>>,
+}
+
+impl AppState {
+    pub fn new(graph: Arc<ModuleGraph>) -> Self {
+        Self {
+            graph: Arc::new(ArcSwap::new(graph)),
+            status: Arc::new(ArcSwap::from_pointee(GraphStatus::Ready)),
+            transpiler: TransformPool::get().clone(),
+            transforms: TransformCache::default(),
+            source_maps: SourceMapOptions::default(),
+            target: Target::default(),
+            warm_up: Default::default(),
+        }
+    }
+
+    pub fn status(&self) -> Arc<GraphStatus> {
+        self.status.load_full()
+    }
+
+    pub fn set_status(&self, status: GraphStatus) {
+        self.status.store(Arc::new(status));
+    }
+
+    /// The current graph, requests in flight keep using the graph they started with
+    pub fn graph(&self) -> Arc<ModuleGraph> {
+        self.graph.load_full()
+    }
+
+    /// Replaces the graph and marks the state as ready
+    pub fn swap_graph(&self, graph: Arc<ModuleGraph>) {
+        self.graph.store(graph);
+        self.transforms.clear();
+        self.set_status(GraphStatus::Ready);
+    }
+
+    /// Loads, verifies and transforms a module of the current graph.
+    /// Concurrent requests for the same module share one transform.
+    pub async fn transform_module(
+        &self,
+        specifier: &ModuleSpecifier,
+        hmr: bool,
+    ) -> Result<TransformResult, TransformError> {
+        self.transforms
+            .single_flight(specifier, hmr, false, || {
+                self.load_and_transform(specifier, hmr, false)
+            })
+            .await
+    }
+
+    /// Transforms the module into the cache behind all module requests
+    async fn warm_module(&self, specifier: &ModuleSpecifier) -> Result<(), TransformError> {
+        self.transforms
+            .single_flight(specifier, false, true, || {
+                self.load_and_transform(specifier, false, true)
+            })
+            .await
+            .map(|_| ())
+    }
+
+    async fn load_and_transform(
+        &self,
+        specifier: &ModuleSpecifier,
+        hmr: bool,
+        background: bool,
+    ) -> Result<TransformResult, TransformError> {
+        let graph = self.graph();
+
+        let module = graph
+            .get_module(specifier)
+            .ok_or_else(|| TransformError::NotFound(specifier.clone()))?
+            .esm()
+            .ok_or_else(|| TransformError::NotEsm(specifier.clone()))?;
+
+        let start = Instant::now();
+        let code = module
+            .load_code()
+            .instrument(debug_span!(target: "api", "load_code"))
+            .await
+            .map_err(TransformError::Read)?;
+        metrics().module_load.observe(start.elapsed());
+
+        if let Some(lockfile) = &graph.lockfile {
+            lockfile
+                .verify_module(specifier, code.as_bytes())
+                .map_err(TransformError::Integrity)?;
+            lockfile
+                .verify_jsr_module(specifier, module.local(), code.as_bytes())
+                .await
+                .map_err(TransformError::Integrity)?;
+        }
+
+        let sha256 = sha256_hex(code.as_bytes());
+        if let Some(result) = self.transforms.get(specifier, hmr, &sha256) {
+            debug!(target: "api", "Transform cache hit");
+            return Ok(result);
+        }
+
+        let priority = if background {
+            Priority::Background
+        } else if graph.is_entry_or_direct_import(specifier) {
+            Priority::High
+        } else {
+            Priority::Normal
+        };
+
+        let result = self
+            .transpiler
+            .schedule(
+                TransformOptions {
+                    code,
+                    hmr,
+                    graph,
+                    module,
+                    source_map: self.source_maps,
+                    target: self.target.clone(),
+                    urls: ModuleUrls::DevServer,
+                },
+                priority,
+            )
+            .await
+            .result()
+            .await
+            .ok_or_else(|| {
+                metrics().transform_failures.inc();
+                TransformError::Failed
+            })?;
+
+        self.transforms.insert(specifier, hmr, sha256, &result);
+
+        Ok(result)
+    }
+}
+
+#[derive(Debug)]
+pub enum TransformError {
+    NotFound(ModuleSpecifier),
+    NotEsm(ModuleSpecifier),
+    Read(std::io::Error),
+    Integrity(IntegrityError),
+    Failed,
+}
+
+impl TransformError {
+    fn status(&self) -> StatusCode {
+        match self {
+            TransformError::NotFound(_) | TransformError::NotEsm(_) => StatusCode::NOT_FOUND,
+            TransformError::Read(_) => StatusCode::BAD_REQUEST,
+            TransformError::Integrity(_) => StatusCode::CONFLICT,
+            TransformError::Failed => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn to_error_response(&self) -> ErrorResponse {
+        let (error, description) = match self {
+            TransformError::NotFound(specifier) => {
+                ("Module not found", Some(specifier.to_string()))
+            }
+            TransformError::NotEsm(specifier) => {
+                ("Module is not an ESM module", Some(specifier.to_string()))
+            }
+            TransformError::Read(e) => ("Failed to read module file", Some(e.to_string())),
+            TransformError::Integrity(e) => ("Integrity check failed", Some(e.to_string())),
+            TransformError::Failed => ("Failed to transform module", None),
+        };
+
+        ErrorResponse {
+            error: error.to_string(),
+            description,
+        }
+    }
+}
+
+impl std::error::Error for TransformError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransformError::Read(e) => Some(e),
+            TransformError::Integrity(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransformError::NotFound(specifier) => write!(f, "Module {} not found", specifier),
+            TransformError::NotEsm(specifier) => write!(f, "{} is not an ESM module", specifier),
+            TransformError::Read(e) => write!(f, "Failed to read module file: {}", e),
+            TransformError::Integrity(e) => write!(f, "{}", e),
+            TransformError::Failed => write!(f, "Failed to transform module"),
+        }
+    }
+}
+
 pub fn router(graph: Arc<ModuleGraph>) -> Router {
-    GRAPH.set(graph).unwrap();
+    router_with_state(AppState::new(graph))
+}
+
+/// Routers are independent, several projects can be served by one process by nesting them
+/// (`Router::new().nest("/project-a", router_with_state(a))`)
+pub fn router_with_state(state: AppState) -> Router {
     Router::new()
-        .route("/", get(|| async { "Hello, World!" }))
+        .route(
+            "/",
+            get(|| async { concat!("deno-plc-build ", env!("CARGO_PKG_VERSION")) }),
+        )
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(get_metrics))
         .route("/api/v1/graph/lookup_imports", get(get_module_lookup_table))
+        .route("/api/v1/graph/entrypoints", get(get_entrypoints))
+        .route("/api/v1/graph/cycles", get(get_cycles))
+        .route("/api/v1/graph/export", get(export_graph))
         .route("/api/v1/transform/module", get(translate_module))
+        .route("/api/v1/html", get(render_html))
         .route("/api/v1/npm/metadata", get(get_npm_metadata))
+        .route("/api/v1/npm/dir", get(get_npm_dir))
+        .route("/api/v1/npm/target", get(get_npm_target))
+        .route("/api/v1/jsr/metadata", get(get_jsr_metadata))
+        .route("/api/v1/workspace", get(get_workspace))
+        .layer(middleware::from_fn(count_requests))
+        .with_state(state)
+}
+
+async fn count_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+    metrics().record_request(&route, response.status().as_u16());
+    response
+}
+
+/// Prometheus text format
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let gauges = Gauges {
+        transform_queue_depth: state.transpiler.queued_count(),
+        transform_waiting: state.transpiler.waiting_count(),
+        transform_active: state.transpiler.active_count(),
+        transform_cache_bytes: state.transforms.bytes(),
+        modules: state.graph().modules().count(),
+    };
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        metrics().render(&gauges),
+    )
 }
 
 async fn get_module_lookup_table(
+    State(state): State<AppState>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<(StatusCode, Json<ModuleLookupTableResponse>), (StatusCode, Json<ErrorResponse>)> {
     let module_id = params.get("module").ok_or((
@@ -48,9 +337,8 @@ async fn get_module_lookup_table(
         )
     })?;
 
-    let table = GRAPH
-        .get()
-        .unwrap()
+    let table = state
+        .graph()
         .get_module(&module_specifier)
         .ok_or_else(|| {
             (
@@ -80,6 +368,112 @@ async fn get_module_lookup_table(
     Ok((StatusCode::OK, Json(ModuleLookupTableResponse { table })))
 }
 
+async fn get_entrypoints(State(state): State<AppState>) -> Json<EntrypointsResponse> {
+    Json(EntrypointsResponse {
+        entrypoints: state
+            .graph()
+            .entrypoints()
+            .iter()
+            .map(|(name, module)| (name.clone(), module.specifier().as_ref().clone()))
+            .collect(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct EntrypointsResponse {
+    entrypoints: BTreeMap<String, ModuleSpecifier>,
+}
+
+async fn get_cycles(State(state): State<AppState>) -> Json<CyclesResponse> {
+    Json(CyclesResponse {
+        cycles: state.graph().find_cycles(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct CyclesResponse {
+    cycles: Vec<ImportCycle>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    Dot,
+    Mermaid,
+    Json,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    format: ExportFormat,
+    entrypoint: Option<String>,
+    depth: Option<usize>,
+    /// Comma separated list of schemes (`file`, `https`, `jsr`, `npm`)
+    scheme: Option<String>,
+    #[serde(default)]
+    collapse: bool,
+}
+
+async fn export_graph(
+    State(state): State<AppState>,
+    Query(params): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let schemes = params
+        .scheme
+        .map(|schemes| {
+            schemes
+                .split(',')
+                .map(|scheme| {
+                    ModuleScheme::parse(scheme.trim()).ok_or_else(|| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            Json(ErrorResponse {
+                                error: "Invalid scheme".to_string(),
+                                description: Some(scheme.to_string()),
+                            }),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()
+        })
+        .transpose()?;
+
+    let graph = state.graph();
+
+    if let Some(entrypoint) = &params.entrypoint
+        && graph.entrypoint(entrypoint).is_none()
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Entrypoint not found".to_string(),
+                description: Some(entrypoint.clone()),
+            }),
+        ));
+    }
+
+    let export = graph.export(&ExportOptions {
+        entrypoint: params.entrypoint,
+        max_depth: params.depth,
+        schemes,
+        collapse_packages: params.collapse,
+    });
+
+    Ok(match params.format {
+        ExportFormat::Dot => (
+            [(header::CONTENT_TYPE, "text/vnd.graphviz; charset=utf-8")],
+            export.to_dot(),
+        )
+            .into_response(),
+        ExportFormat::Mermaid => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            export.to_mermaid(),
+        )
+            .into_response(),
+        ExportFormat::Json => Json(export).into_response(),
+    })
+}
+
 #[derive(Debug, Serialize)]
 struct ErrorResponse {
     error: String,
@@ -91,72 +485,115 @@ struct ModuleLookupTableResponse {
     table: HashMap<String, String>,
 }
 
+#[instrument(target = "api", level = "debug", skip_all, fields(module = %params.module))]
 async fn translate_module(
+    State(state): State<AppState>,
     Query(params): Query<TranslateModuleQuery>,
-    // Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<TranslateModuleOutput>, (StatusCode, Json<ErrorResponse>)> {
-    let graph = GRAPH.get().unwrap().clone();
-    let module_id = params.module;
-    // let module_id = ModuleSpecifier::parse(params.get("module").unwrap()).unwrap();
-
-    let module = graph
-        .get_module(&module_id)
-        .ok_or_else(|| {
-            (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "Module not found".to_string(),
-                    description: Some(module_id.to_string()),
-                }),
-            )
-        })?
-        .esm()
-        .ok_or_else(|| {
-            (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "Module is not an ESM module".to_string(),
-                    description: Some(module_id.to_string()),
-                }),
-            )
-        })?;
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    // only the modules of the project are replaced, the others are reloaded with the page
+    let hmr = params.hmr && params.module.scheme() == "file";
+    match state.transform_module(&params.module, hmr).await {
+        Ok(result) => {
+            let mut response = Json(TranslateModuleOutput { result }).into_response();
+            if let Some(link) = preload_header(&state.graph(), &params.module) {
+                response.headers_mut().insert(header::LINK, link);
+            }
+            Ok(response)
+        }
+        Err(err) => Err((err.status(), Json(err.to_error_response()))),
+    }
+}
 
-    let code = module.load_code().await.map_err(|e| {
+/// Modules listed in the `Link` header of a transformed module
+const PRELOAD_LIMIT: usize = 50;
+
+/// `Link: </a.ts>; rel=modulepreload, ...` for the transitive imports of the module, so the browser fetches
+/// them in parallel instead of discovering them one level at a time. The dev server forwards the header.
+fn preload_header(graph: &ModuleGraph, specifier: &ModuleSpecifier) -> Option<HeaderValue> {
+    let links = graph
+        .preload(specifier, PRELOAD_LIMIT)
+        .iter()
+        .map(|module| format!("<{}>; rel=modulepreload", module_url(graph, module)))
+        .collect::<Vec<_>>();
+
+    if links.is_empty() {
+        return None;
+    }
+
+    HeaderValue::from_str(&links.join(", ")).ok()
+}
+
+/// HTML page of the project with rewritten module scripts
+async fn render_html(
+    State(state): State<AppState>,
+    Query(params): Query<RenderHtmlQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let not_found = || {
         (
-            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
             Json(ErrorResponse {
-                error: "Failed to read module file".to_string(),
-                description: Some(e.to_string()),
+                error: "Page not found".to_string(),
+                description: Some(params.page.to_string()),
             }),
         )
-    })?;
+    };
 
-    let res = TransformPool::get()
-        .transform(TransformOptions {
-            code,
-            hmr: false,
-            graph,
-            module,
-        })
-        .result()
-        .await;
+    let graph = state.graph();
+
+    // only HTML files inside the project
+    let path = params.page.to_file_path().map_err(|_| not_found())?.clean();
+    if !path.starts_with(graph.root_dir.clean())
+        || path.extension().is_none_or(|extension| extension != "html")
+        || !path.is_file()
+    {
+        return Err(not_found());
+    }
+
+    let render = async {
+        let page = HtmlPage::load(&path, &graph.root_dir).await?;
+        page.render(
+            graph.clone(),
+            &state.transpiler,
+            &state.target,
+            params.dev,
+            ModuleUrls::DevServer,
+        )
+        .await
+    };
 
-    if let Some(res) = res {
-        Ok(Json(TranslateModuleOutput { result: res }))
-    } else {
-        Err((
+    match render.await {
+        Ok(html) => Ok((
+            [
+                (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+                (header::CACHE_CONTROL, "no-store"),
+            ],
+            html,
+        )
+            .into_response()),
+        Err(err) => Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
-                error: "Failed to transform module".to_string(),
-                description: None,
+                error: "Failed to render page".to_string(),
+                description: Some(err),
             }),
-        ))
+        )),
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct RenderHtmlQuery {
+    page: ModuleSpecifier,
+    /// Inject the HMR client
+    #[serde(default)]
+    dev: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct TranslateModuleQuery {
     module: ModuleSpecifier,
+    /// Register the module with the HMR client
+    #[serde(default)]
+    hmr: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -165,9 +602,10 @@ struct TranslateModuleOutput {
 }
 
 async fn get_npm_metadata(
+    State(state): State<AppState>,
     Query(package_id): Query<NPMPackageId>,
 ) -> Result<Json<NPMMetadataResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let graph = GRAPH.get().unwrap().clone();
+    let graph = state.graph();
     let pkg = graph
         .get_npm_package(&package_id.to_string())
         .ok_or_else(|| {
@@ -180,14 +618,126 @@ async fn get_npm_metadata(
             )
         })?;
 
+    if let (Some(lockfile), Some(npm_dir)) = (&graph.lockfile, &graph.npm_dir) {
+        let registry_integrity = pkg.registry_integrity(npm_dir).await;
+        lockfile
+            .verify_npm_metadata(&pkg.id().to_string(), registry_integrity.as_deref())
+            .map_err(|e| {
+                (
+                    StatusCode::CONFLICT,
+                    Json(ErrorResponse {
+                        error: "Integrity check failed".to_string(),
+                        description: Some(e.to_string()),
+                    }),
+                )
+            })?;
+    }
+
     Ok(Json(NPMMetadataResponse {
         registry_url: pkg.registry_url().clone(),
         dependencies: pkg.dependencies(),
+        integrity: graph
+            .lockfile
+            .as_ref()
+            .and_then(|lockfile| lockfile.npm_integrity(&pkg.id().to_string()))
+            .map(|integrity| integrity.to_string()),
     }))
 }
 
+#[derive(Debug, Serialize)]
+struct NPMDirResponse {
+    /// Vendored packages or the deno npm cache, `None` if the location is unknown to the graph server
+    dir: Option<PathBuf>,
+}
+
+async fn get_npm_dir(State(state): State<AppState>) -> Json<NPMDirResponse> {
+    Json(NPMDirResponse {
+        dir: state.graph().npm_dir.clone(),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct NPMTargetResponse {
+    /// `target` option of esbuild
+    esbuild: Vec<String>,
+}
+
+/// Target of the npm packages, which are compiled with esbuild by the launcher
+async fn get_npm_target(
+    State(state): State<AppState>,
+) -> Result<Json<NPMTargetResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let esbuild = state.target.esbuild().map_err(|err| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: err,
+                description: None,
+            }),
+        )
+    })?;
+    Ok(Json(NPMTargetResponse { esbuild }))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct NPMMetadataResponse {
     registry_url: Url,
     dependencies: Vec<NPMPackageId>,
+    /// Tarball integrity from the lockfile
+    integrity: Option<String>,
+}
+
+async fn get_jsr_metadata(
+    State(state): State<AppState>,
+    Query(package_id): Query<JsrPackageId>,
+) -> Result<Json<JsrMetadataResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let graph = state.graph();
+    let pkg = graph
+        .get_jsr_package(&package_id.to_string())
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Package not found".to_string(),
+                    description: None,
+                }),
+            )
+        })?;
+
+    Ok(Json(JsrMetadataResponse {
+        name: pkg.id().name.clone(),
+        version: pkg.id().version.clone(),
+        exports: pkg
+            .exports()
+            .iter()
+            .map(|(subpath, module)| (subpath.clone(), module.as_ref().clone()))
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsrMetadataResponse {
+    name: String,
+    version: String,
+    exports: BTreeMap<String, ModuleSpecifier>,
+}
+
+async fn get_workspace(State(state): State<AppState>) -> Json<Workspace> {
+    Json(state.graph().workspace.clone())
+}
+
+#[test]
+fn test_independent_routers() {
+    let a = AppState::new(Arc::new(ModuleGraph::new()));
+    let b = AppState::new(Arc::new(ModuleGraph::new()));
+
+    let _router = Router::new()
+        .nest("/a", router_with_state(a.clone()))
+        .nest("/b", router_with_state(b.clone()));
+
+    let mut graph = ModuleGraph::new();
+    graph.root_dir = "swapped".into();
+    a.swap_graph(Arc::new(graph));
+
+    assert_eq!(a.graph().root_dir, std::path::PathBuf::from("swapped"));
+    assert_eq!(b.graph().root_dir, std::path::PathBuf::new());
 }
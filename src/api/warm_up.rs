@@ -0,0 +1,114 @@
+//! Optional warm-up: transforms the reachable graph in the background so the first page load hits the cache.
+
+use std::{sync::Arc, time::Instant};
+
+use serde::Serialize;
+use tokio::task::JoinSet;
+use tracing::{debug, info};
+
+use super::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmUpStatus {
+    Running,
+    Done,
+    /// The graph was swapped before all modules were transformed
+    Cancelled,
+}
+
+/// Reported by `/readyz`, the server is ready independent of the warm-up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WarmUpProgress {
+    pub status: WarmUpStatus,
+    /// Reachable ESM modules
+    pub total: usize,
+    pub transformed: usize,
+    pub failed: usize,
+}
+
+impl AppState {
+    pub fn warm_up_progress(&self) -> Option<WarmUpProgress> {
+        self.warm_up.lock().unwrap().clone()
+    }
+
+    fn update_warm_up(&self, update: impl FnOnce(&mut WarmUpProgress)) {
+        if let Some(progress) = self.warm_up.lock().unwrap().as_mut() {
+            update(progress);
+        }
+    }
+
+    pub fn spawn_warm_up(&self) {
+        tokio::spawn(self.clone().warm_up());
+    }
+
+    /// Transforms every ESM module reachable from the entrypoints (in BFS order) into the transform cache.
+    /// Module requests of the browser take precedence.
+    pub async fn warm_up(self) {
+        let graph = self.graph();
+        let start = Instant::now();
+
+        let mut modules = graph
+            .walk(None)
+            .into_iter()
+            .filter_map(|module| module.esm())
+            .map(|module| module.specifier())
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        *self.warm_up.lock().unwrap() = Some(WarmUpProgress {
+            status: WarmUpStatus::Running,
+            total: modules.len(),
+            transformed: 0,
+            failed: 0,
+        });
+
+        // keeps the workers busy without taking all queue slots
+        let concurrency = self.transpiler.threads();
+        let mut tasks = JoinSet::new();
+
+        loop {
+            while tasks.len() < concurrency {
+                let Some(specifier) = modules.next() else {
+                    break;
+                };
+                let state = self.clone();
+                tasks.spawn(async move {
+                    let result = state.warm_module(&specifier).await;
+                    (specifier, result)
+                });
+            }
+
+            let Some(result) = tasks.join_next().await else {
+                break;
+            };
+
+            if !Arc::ptr_eq(&graph, &self.graph()) {
+                tasks.abort_all();
+                self.update_warm_up(|progress| progress.status = WarmUpStatus::Cancelled);
+                info!(target: "api", "Graph changed, warm-up cancelled");
+                return;
+            }
+
+            match result {
+                Ok((_, Ok(_))) => self.update_warm_up(|progress| progress.transformed += 1),
+                Ok((specifier, Err(err))) => {
+                    debug!(target: "api", "Warm-up of {} failed: {}", specifier, err);
+                    self.update_warm_up(|progress| progress.failed += 1);
+                }
+                Err(_) => self.update_warm_up(|progress| progress.failed += 1),
+            }
+        }
+
+        self.update_warm_up(|progress| progress.status = WarmUpStatus::Done);
+
+        if let Some(progress) = self.warm_up_progress() {
+            info!(target: "api",
+                "Warm-up transformed {} modules in {} ms ({} failed)",
+                progress.transformed,
+                start.elapsed().as_millis(),
+                progress.failed
+            );
+        }
+    }
+}
@@ -1,47 +1,480 @@
-use std::env;
+use std::{collections::BTreeMap, env, net::IpAddr, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::specifier::ModuleSpecifier;
+use crate::{logging::LogFormat, specifier::ModuleSpecifier};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub root_path: String,
-    pub root_module: ModuleSpecifier,
+    /// Single root module, added to the entrypoints as `root`
+    #[serde(default)]
+    pub root_module: Option<ModuleSpecifier>,
+    /// Named entrypoints (e.g. panel client, admin client, service worker)
+    #[serde(default)]
+    pub entrypoints: BTreeMap<String, ModuleSpecifier>,
+    /// HTML entrypoints (relative to root_path), their module scripts are added to the entrypoints.
+    /// Default: `index.html` if present
+    #[serde(default)]
+    pub html: Option<Vec<String>>,
+    /// 0 picks a free port, the actual port is part of the ready handshake
     pub port: u16,
+    /// Address to listen on (ex. `127.0.0.1`, `::` for any). Default: `::1`, falling back to `127.0.0.1`
+    /// if IPv6 is unavailable
+    #[serde(default)]
+    pub bind_address: Option<IpAddr>,
+    /// Listen on this Unix domain socket instead of TCP. For embedding (`DevServer`) and other local clients,
+    /// the launcher (`mod.ts`) only connects over TCP
+    #[serde(default)]
+    pub unix_socket: Option<String>,
+    /// JSON file containing the known import cycles (`Vec<ImportCycle>`)
+    #[serde(default)]
+    pub cycle_baseline: Option<String>,
+    /// Exit with an error from the `cycles` command if cycles outside the baseline are found
+    #[serde(default)]
+    pub fail_on_new_cycles: bool,
+    /// Resolve the graph from this vendor directory (relative to root_path) instead of calling `deno info`.
+    /// Also the output directory of the `vendor` command (default: `vendor`)
+    #[serde(default)]
+    pub vendor_dir: Option<String>,
+    /// Lockfile to verify served remote modules against (relative to root_path, default: `deno.lock` if present)
+    #[serde(default)]
+    pub lockfile: Option<String>,
+    #[serde(default)]
+    pub graph_backend: GraphBackend,
+    /// Deno cache directory for the native backend (default: `$DENO_DIR` or the platform cache dir)
+    #[serde(default)]
+    pub deno_dir: Option<String>,
+    /// Saved `deno info --json` output for the `info_file` backend (relative to root_path)
+    #[serde(default)]
+    pub info_file: Option<String>,
+    /// Snapshot of the graph (relative to root_path), reused on the next start if local files are unchanged
+    #[serde(default)]
+    pub graph_snapshot: Option<String>,
+    /// Serve the API over HTTPS (requires the `tls` feature, enabled by default).
+    /// The launcher sets it from `graph_server_tls`
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Cache for generated files like the self-signed certificate
+    /// (default: `deno-plc-build` in the platform cache dir)
+    #[serde(default)]
+    pub cache_dir: Option<String>,
+    /// Exit when this process (the launcher) exits. Also `--parent-pid <pid>`
+    #[serde(default)]
+    pub parent_pid: Option<u32>,
+    /// Exit when stdin is closed, the launcher keeps it open. Also `--watch-stdin`
+    #[serde(default)]
+    pub watch_stdin: bool,
+    /// Log filter (ex. `debug`, `info,graph=debug,transpiler=trace`), overridden by `DENO_PLC_BUILD_LOG`.
+    /// Also `--log-level <filter>`
+    #[serde(default)]
+    pub log_level: Option<String>,
+    /// `text` or `json` (one JSON object per line on stderr). Also `--log-json`
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Worker threads transforming modules (default: number of CPUs)
+    #[serde(default)]
+    pub transform_threads: Option<usize>,
+    /// Transforms that may be queued or running, further module requests wait for a slot (default: 256)
+    #[serde(default)]
+    pub transform_queue: Option<usize>,
+    /// Megabytes of transformed modules kept in memory, the least recently used are evicted (default: 256)
+    #[serde(default)]
+    pub transform_cache_size: Option<usize>,
+    /// Transform all reachable modules in the background once the graph is built, progress is reported
+    /// on `/readyz`. Graphs larger than `transform_cache_size` are only partially kept
+    #[serde(default)]
+    pub warm_up: bool,
+    /// Source maps of transformed modules
+    #[serde(default)]
+    pub source_maps: SourceMapOptions,
+    /// Syntax the browsers support: `es2017` .. `es2022`, `esnext` (default, no lowering) or a browserslist
+    /// query (ex. `chrome >= 61`)
+    #[serde(default)]
+    pub target: Target,
 }
 
-pub fn from_args() -> Config {
-    let args = env::args().collect::<Vec<_>>();
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain (relative to root_path), a self-signed certificate is generated if `cert` or
+    /// `key` is missing
+    #[serde(default)]
+    pub cert: Option<String>,
+    /// PEM private key (relative to root_path)
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Additional host names and IPs of the generated certificate (ex. the address of this machine on the
+    /// plant network). `localhost`, `127.0.0.1` and `::1` are always included.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceMapOptions {
+    pub mode: SourceMapMode,
+    /// Embed the original source (`sourcesContent`), needed if the browser can't fetch the `sources`
+    pub sources_content: bool,
+    pub sources: SourceMapSources,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceMapMode {
+    /// Data URL at the end of the code
+    #[default]
+    Inline,
+    /// Served as `<module url>.map`, the map is part of the transform result
+    External,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceMapSources {
+    /// Original module URL (`file:///...`, `https://...`)
+    #[default]
+    Url,
+    /// Local modules relative to root_path (`/src/a.ts`, resolved against the origin of the server),
+    /// remote modules by URL
+    Relative,
+}
+
+/// Newer syntax is lowered with swc's `preset_env`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    Es2017,
+    Es2018,
+    Es2019,
+    Es2020,
+    Es2021,
+    Es2022,
+    #[default]
+    EsNext,
+    Browserslist(String),
+}
+
+impl Target {
+    /// Browserslist query of the target, `None` if nothing has to be lowered.
+    /// ES versions map to the first browser versions that fully support them.
+    pub fn browserslist(&self) -> Option<String> {
+        let query = match self {
+            Target::Es2017 => "chrome 58, edge 16, firefox 53, safari 11",
+            Target::Es2018 => "chrome 64, edge 79, firefox 78, safari 16.4",
+            Target::Es2019 => "chrome 73, edge 79, firefox 78, safari 16.4",
+            Target::Es2020 => "chrome 80, edge 80, firefox 80, safari 16.4",
+            Target::Es2021 => "chrome 85, edge 85, firefox 80, safari 16.4",
+            Target::Es2022 => "chrome 94, edge 94, firefox 93, safari 16.4",
+            Target::EsNext => return None,
+            Target::Browserslist(query) => query,
+        };
+        Some(query.to_string())
+    }
+
+    /// `target` option of esbuild (used for npm packages): the ES version, or the oldest version of each
+    /// browser matched by the query. Browsers esbuild doesn't know (ex. Samsung Internet) are skipped.
+    pub fn esbuild(&self) -> Result<Vec<String>, String> {
+        let query = match self {
+            Target::Browserslist(query) => query,
+            target => return Ok(vec![String::from(target.clone())]),
+        };
+
+        let browsers = browserslist::resolve([query.as_str()], &Default::default())
+            .map_err(|e| format!("Invalid target {}: {}", query, e))?;
+
+        let mut oldest: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+        for browser in &browsers {
+            let engine = match browser.name() {
+                "chrome" | "and_chr" => "chrome",
+                "edge" => "edge",
+                "firefox" | "and_ff" => "firefox",
+                "safari" => "safari",
+                "ios_saf" => "ios",
+                "opera" => "opera",
+                "ie" => "ie",
+                "node" => "node",
+                _ => continue,
+            };
+            // ranges like `ios_saf 11.0-11.2`
+            let version = browser.version().split('-').next().unwrap_or_default();
+            let Ok(version) = version
+                .split('.')
+                .map(str::parse)
+                .collect::<Result<Vec<u32>, _>>()
+            else {
+                // `safari TP`
+                continue;
+            };
+
+            let entry = oldest.entry(engine).or_insert_with(|| version.clone());
+            if version < *entry {
+                *entry = version;
+            }
+        }
+
+        if oldest.is_empty() {
+            return Ok(vec![String::from(Target::EsNext)]);
+        }
+
+        Ok(oldest
+            .into_iter()
+            .map(|(engine, version)| {
+                let version = version
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".");
+                format!("{}{}", engine, version)
+            })
+            .collect())
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(match value.to_lowercase().as_str() {
+            "es2017" => Target::Es2017,
+            "es2018" => Target::Es2018,
+            "es2019" => Target::Es2019,
+            "es2020" => Target::Es2020,
+            "es2021" => Target::Es2021,
+            "es2022" => Target::Es2022,
+            "esnext" => Target::EsNext,
+            _ => {
+                browserslist::resolve([value.as_str()], &Default::default())
+                    .map_err(|e| format!("Invalid target {}: {}", value, e))?;
+                Target::Browserslist(value)
+            }
+        })
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Es2017 => "es2017".to_string(),
+            Target::Es2018 => "es2018".to_string(),
+            Target::Es2019 => "es2019".to_string(),
+            Target::Es2020 => "es2020".to_string(),
+            Target::Es2021 => "es2021".to_string(),
+            Target::Es2022 => "es2022".to_string(),
+            Target::EsNext => "esnext".to_string(),
+            Target::Browserslist(query) => query,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphBackend {
+    /// Spawn `deno info --json`
+    #[default]
+    DenoInfo,
+    /// Build the graph in-process with `deno_graph` (requires the `native` feature)
+    Native,
+    /// Read a saved `deno info --json` output from `info_file`
+    InfoFile,
+}
+
+impl Config {
+    pub fn entrypoints(&self) -> BTreeMap<String, ModuleSpecifier> {
+        let mut entrypoints = self.entrypoints.clone();
+        if let Some(root_module) = &self.root_module {
+            entrypoints
+                .entry("root".to_string())
+                .or_insert_with(|| root_module.clone());
+        }
+        entrypoints
+    }
+
+    pub fn html_entrypoints(&self) -> Vec<PathBuf> {
+        let root_dir = PathBuf::from(&self.root_path);
+        match &self.html {
+            Some(pages) => pages.iter().map(|page| root_dir.join(page)).collect(),
+            None => Some(root_dir.join("index.html"))
+                .filter(|page| page.exists())
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub fn lockfile(&self) -> PathBuf {
+        PathBuf::from(&self.root_path).join(self.lockfile.as_deref().unwrap_or("deno.lock"))
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        match &self.cache_dir {
+            Some(dir) => PathBuf::from(&self.root_path).join(dir),
+            None => user_cache_dir()
+                .map(|dir| dir.join("deno-plc-build"))
+                .unwrap_or_else(|| PathBuf::from(&self.root_path).join(".deno-plc-build")),
+        }
+    }
+
+    pub fn graph_snapshot(&self) -> Option<PathBuf> {
+        self.graph_snapshot
+            .as_ref()
+            .map(|path| PathBuf::from(&self.root_path).join(path))
+    }
+
+    pub fn vendor_dir(&self) -> PathBuf {
+        PathBuf::from(&self.root_path).join(self.vendor_dir.as_deref().unwrap_or("vendor"))
+    }
+}
+
+/// Platform cache directory (`%LOCALAPPDATA%`, `~/Library/Caches`, `$XDG_CACHE_HOME` or `~/.cache`)
+pub fn user_cache_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run the graph server (default)
+    Serve,
+    /// Report import cycles among local modules and exit
+    Cycles,
+    /// Copy all remote modules and npm packages into the vendor directory
+    Vendor,
+    /// Write a bundle size report (JSON + HTML treemap) to the given directory (default: root_path)
+    Size { out_dir: Option<PathBuf> },
+    /// Write the processed HTML entrypoints and the transformed modules they load to the given directory
+    /// (default: `dist` in root_path), a static site
+    Html { out_dir: Option<PathBuf> },
+}
+
+impl Command {
+    fn parse(args: &[String]) -> Command {
+        match args.first().map(|arg| arg.as_str()) {
+            None | Some("serve") => Command::Serve,
+            Some("cycles") => Command::Cycles,
+            Some("vendor") => Command::Vendor,
+            Some("size") => Command::Size {
+                out_dir: args.get(1).map(PathBuf::from),
+            },
+            Some("html") => Command::Html {
+                out_dir: args.get(1).map(PathBuf::from),
+            },
+            Some(other) => panic!("Invalid command: {}", other),
+        }
+    }
+}
+
+pub fn from_args() -> (Config, Command) {
+    let mut args = env::args().collect::<Vec<_>>();
+
+    // flags may appear anywhere
+    let parent_pid = take_option(&mut args, "--parent-pid")
+        .map(|pid| pid.parse::<u32>().expect("Invalid parent pid"));
+    let watch_stdin = take_flag(&mut args, "--watch-stdin");
+    let log_level = take_option(&mut args, "--log-level");
+    let log_json = take_flag(&mut args, "--log-json");
 
     let cfg_type = args.get(1).expect("No config specified");
 
-    let cfg = match cfg_type.as_str() {
-        "--json" => serde_json::from_str(args.get(2).unwrap()).unwrap(),
+    let (mut cfg, command): (Config, Command) = match cfg_type.as_str() {
+        "--json" => (
+            serde_json::from_str(args.get(2).unwrap()).unwrap(),
+            Command::parse(args.get(3..).unwrap_or_default()),
+        ),
         "--technik-app" => {
             let root_path = env::current_dir().unwrap().join("../technik-app");
-            Config {
+            let cfg = Config {
                 root_path: root_path.to_string_lossy().to_string(),
-                root_module: ModuleSpecifier::from_file_path(
-                    root_path.join("frontend/dev.client.tsx"),
-                )
-                .unwrap(),
+                root_module: None,
+                entrypoints: BTreeMap::from([(
+                    "client".to_string(),
+                    ModuleSpecifier::from_file_path(root_path.join("frontend/dev.client.tsx"))
+                        .unwrap(),
+                )]),
+                html: None,
                 port: 3000,
-            }
+                bind_address: None,
+                unix_socket: None,
+                cycle_baseline: None,
+                fail_on_new_cycles: false,
+                vendor_dir: None,
+                lockfile: None,
+                graph_backend: GraphBackend::DenoInfo,
+                deno_dir: None,
+                info_file: None,
+                graph_snapshot: None,
+                tls: None,
+                cache_dir: None,
+                parent_pid: None,
+                watch_stdin: false,
+                log_level: None,
+                log_format: LogFormat::Text,
+                transform_threads: None,
+                transform_queue: None,
+                transform_cache_size: None,
+                warm_up: false,
+                source_maps: SourceMapOptions::default(),
+                target: Target::EsNext,
+            };
+            (cfg, Command::parse(args.get(2..).unwrap_or_default()))
         }
         _ => {
             panic!("Invalid config type")
         }
     };
 
-    println!(
-        "             INF app·deno-plc·build root_path = {}",
-        cfg.root_path
-    );
-    println!(
-        "             INF app·deno-plc·build root_module = {}",
-        cfg.root_module
+    cfg.parent_pid = cfg.parent_pid.or(parent_pid);
+    cfg.watch_stdin |= watch_stdin;
+    cfg.log_level = log_level.or(cfg.log_level);
+    if log_json {
+        cfg.log_format = LogFormat::Json;
+    }
+
+    (cfg, command)
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(pos) => {
+            args.remove(pos);
+            true
+        }
+        None => false,
+    }
+}
+
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let pos = args.iter().position(|arg| arg == name)?;
+    let value = args
+        .get(pos + 1)
+        .unwrap_or_else(|| panic!("{} requires a value", name))
+        .clone();
+    args.drain(pos..pos + 2);
+    Some(value)
+}
+
+#[test]
+fn test_target() {
+    assert_eq!(Target::try_from("ES2017".to_string()), Ok(Target::Es2017));
+    assert_eq!(Target::EsNext.browserslist(), None);
+    assert_eq!(
+        Target::try_from("chrome >= 61".to_string()),
+        Ok(Target::Browserslist("chrome >= 61".to_string()))
     );
+    assert!(Target::try_from("not a browser 1".to_string()).is_err());
+}
 
-    cfg
+#[test]
+fn test_esbuild_target() {
+    assert_eq!(Target::Es2020.esbuild(), Ok(vec!["es2020".to_string()]));
+    assert_eq!(
+        Target::Browserslist("chrome >= 61, safari 11.1, samsung 8.2".to_string()).esbuild(),
+        Ok(vec!["chrome61".to_string(), "safari11.1".to_string()])
+    );
 }
@@ -44,10 +44,31 @@ export interface BuildConfig {
     entrypoint?: URL;
 
     /**
-     * Port for the graph server to use (this is not the dev server)
+     * Additional named entrypoints (e.g. admin client, service worker). `root_module` is always added as `root`
+     */
+    entrypoints?: Record<string, URL>;
+
+    /**
+     * Port for the graph server to use (this is not the dev server). 0 picks a free port
      */
     graph_server_port?: number;
 
+    /**
+     * Address the graph server listens on (ex. `127.0.0.1`, `::` for any).
+     * Defaults to `::1`, falling back to `127.0.0.1` if IPv6 is unavailable
+     */
+    graph_server_host?: string;
+
+    /**
+     * Serve the graph API over HTTPS. `true` generates a self-signed certificate (cached on disk, trusted by
+     * the launcher), `hosts` adds names and IPs to it. `cert` and `key` are PEM files relative to `root_dir`
+     */
+    graph_server_tls?: boolean | {
+        cert?: string;
+        key?: string;
+        hosts?: string[];
+    };
+
     /**
      * Set this to false to bring your own graph server
      */
@@ -62,6 +83,22 @@ export interface BuildConfig {
      * List of npm packages that are redirected to esm.sh
      */
     cdn?: string[];
+
+    /**
+     * `inline` appends the source map as a data URL, `external` serves it as `<module url>.map`
+     */
+    source_maps?: "inline" | "external" | "none";
+
+    /**
+     * Syntax the browsers support: `es2017` .. `es2022`, `esnext` (no lowering) or a browserslist query (ex. `chrome >= 61`)
+     */
+    target?: string;
+
+    /**
+     * Hot module replacement for the modules of the project. Components are swapped when `@prefresh/core` is
+     * loaded, other modules need `import.meta.hot.accept()`, otherwise the page reloads
+     */
+    hmr?: boolean;
 }
 
 export type FullConfig = Required<BuildConfig>;
@@ -80,9 +117,15 @@ export function config_defaults(config: BuildConfig): FullConfig {
         logger: getLogger(["app", "deno-plc", "build"]),
         cdn: [],
         graph_server_port: 3000,
+        graph_server_host: "",
+        graph_server_tls: false,
         entrypoint: config.root_module,
+        entrypoints: {},
         run_graph_server: true,
         dev_use_cargo: false,
+        source_maps: "inline",
+        target: "esnext",
+        hmr: true,
 
         ...config,
 
@@ -94,7 +137,33 @@ export function config_defaults(config: BuildConfig): FullConfig {
 export function toServerConfig(config: FullConfig): string {
     return JSON.stringify({
         port: config.graph_server_port,
+        bind_address: config.graph_server_host || undefined,
+        tls: config.graph_server_tls === true
+            ? {}
+            : config.graph_server_tls || undefined,
         root_path: config.root_dir,
         root_module: config.root_module.href,
+        entrypoints: Object.fromEntries(
+            Object.entries(config.entrypoints).map((
+                [name, url],
+            ) => [name, url.href]),
+        ),
+        source_maps: { mode: config.source_maps },
+        target: config.target,
     });
 }
+
+/**
+ * URL of a graph server that is not started by us (`run_graph_server: false`)
+ */
+export function graphServerUrl(config: FullConfig): URL {
+    const host = config.graph_server_host || "::1";
+    const loopback = host === "::"
+        ? "::1"
+        : host === "0.0.0.0"
+        ? "127.0.0.1"
+        : host;
+    const hostname = loopback.includes(":") ? `[${loopback}]` : loopback;
+    const protocol = config.graph_server_tls ? "https" : "http";
+    return new URL(`${protocol}://${hostname}:${config.graph_server_port}`);
+}
@@ -1,15 +1,15 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
 use crate::specifier::ModuleSpecifier;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 use super::{info_preflight::PreflightInfo, media_type::MediaType};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DenoInfo {
     version: u8,
@@ -20,7 +20,7 @@ pub struct DenoInfo {
     pub redirects: HashMap<ModuleSpecifier, ModuleSpecifier>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "kind")]
 #[non_exhaustive]
@@ -47,6 +47,15 @@ impl Module {
         }
     }
 
+    pub fn specifier_mut(&mut self) -> &mut ModuleSpecifier {
+        match self {
+            Module::Esm(module) => &mut module.specifier,
+            Module::Npm(module) => &mut module.specifier,
+            Module::Node(module) => &mut module.specifier,
+            Module::External(module) => &mut module.specifier,
+        }
+    }
+
     pub fn media_type(&self) -> MediaType {
         match self {
             Module::Esm(module) => module.media_type,
@@ -62,37 +71,42 @@ impl Module {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EsmModule {
     pub specifier: ModuleSpecifier,
     pub media_type: MediaType,
     pub local: PathBuf,
+    #[serde(default)]
+    pub size: u64,
     #[serde(default = "Vec::new")]
     pub dependencies: Vec<EsmDependency>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct EsmDependency {
     pub specifier: String,
     pub code: Option<EsmDependencyCode>,
+    /// `import()`, not needed to evaluate the module
+    #[serde(default)]
+    pub is_dynamic: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct EsmDependencyCode {
     pub specifier: ModuleSpecifier,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NpmModule {
     pub specifier: ModuleSpecifier,
     pub npm_package: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BuiltInNodeModule {
     /// Specifier (ex. "node:fs")
@@ -101,13 +115,13 @@ pub struct BuiltInNodeModule {
     pub module_name: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExternalModule {
     pub specifier: ModuleSpecifier,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NpmPackage {
     pub name: String,
@@ -116,9 +130,140 @@ pub struct NpmPackage {
     pub registry_url: Url,
 }
 
-pub async fn call_deno_info(
+impl DenoInfo {
+    pub fn new(
+        roots: Vec<ModuleSpecifier>,
+        modules: Vec<Module>,
+        npm_packages: HashMap<String, NpmPackage>,
+        packages: HashMap<String, String>,
+        redirects: HashMap<ModuleSpecifier, ModuleSpecifier>,
+    ) -> Self {
+        Self {
+            version: 1,
+            roots,
+            modules,
+            npm_packages,
+            packages,
+            redirects,
+        }
+    }
+
+    /// Merges the graph of another root into this one. Modules and packages contained in both are kept once.
+    pub fn merge(&mut self, other: DenoInfo) {
+        let known = self
+            .modules
+            .iter()
+            .map(|m| m.specifier().clone())
+            .collect::<HashSet<_>>();
+
+        for root in other.roots {
+            if !self.roots.contains(&root) {
+                self.roots.push(root);
+            }
+        }
+        self.modules.extend(
+            other
+                .modules
+                .into_iter()
+                .filter(|m| !known.contains(m.specifier())),
+        );
+        self.npm_packages.extend(other.npm_packages);
+        self.packages.extend(other.packages);
+        self.redirects.extend(other.redirects);
+    }
+
+    /// Like `merge`, but modules contained in both are replaced by the version in `other`.
+    /// The roots are kept, `other` is expected to be the graph of modules that changed.
+    pub fn update(&mut self, other: DenoInfo) {
+        let updated = other
+            .modules
+            .iter()
+            .map(|m| m.specifier().clone())
+            .collect::<HashSet<_>>();
+
+        self.modules.retain(|m| !updated.contains(m.specifier()));
+        self.modules.extend(other.modules);
+        self.npm_packages.extend(other.npm_packages);
+        self.packages.extend(other.packages);
+        self.redirects.extend(other.redirects);
+    }
+
+    /// Removes the modules, redirects and npm packages that are no longer reachable from the roots,
+    /// ex. imports that were dropped by an `update`
+    pub fn prune(&mut self) {
+        let modules = self
+            .modules
+            .iter()
+            .map(|m| (m.specifier().clone(), m))
+            .collect::<HashMap<_, _>>();
+
+        let mut reachable = HashSet::new();
+        let mut redirects = HashSet::new();
+        let mut packages = HashSet::new();
+        let mut stack = self.roots.clone();
+
+        while let Some(mut specifier) = stack.pop() {
+            while let Some(target) = self.redirects.get(&specifier) {
+                if !redirects.insert(specifier.clone()) {
+                    break;
+                }
+                specifier = target.clone();
+            }
+
+            if !reachable.insert(specifier.clone()) {
+                continue;
+            }
+
+            match modules.get(&specifier) {
+                Some(Module::Esm(module)) => stack.extend(
+                    module
+                        .dependencies
+                        .iter()
+                        .filter_map(|dep| dep.code.as_ref())
+                        .map(|code| code.specifier.clone()),
+                ),
+                Some(Module::Npm(module)) => {
+                    let mut stack = vec![module.npm_package.clone()];
+                    while let Some(package) = stack.pop() {
+                        if let Some(npm) = self.npm_packages.get(&package)
+                            && packages.insert(package)
+                        {
+                            stack.extend(npm.dependencies.iter().cloned());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.modules.retain(|m| reachable.contains(m.specifier()));
+        self.redirects.retain(|from, _| redirects.contains(from));
+        self.npm_packages.retain(|id, _| packages.contains(id));
+    }
+}
+
+/// Calls `deno info` for every root and merges the results
+pub async fn call_deno_info<'a>(
     deno_executable: &str,
     dir: impl AsRef<Path>,
+    specifiers: impl IntoIterator<Item = &'a ModuleSpecifier>,
+) -> Result<DenoInfo, String> {
+    let mut merged: Option<DenoInfo> = None;
+
+    for specifier in specifiers {
+        let info = call_deno_info_single(deno_executable, dir.as_ref(), specifier).await?;
+        match &mut merged {
+            Some(merged) => merged.merge(info),
+            None => merged = Some(info),
+        }
+    }
+
+    merged.ok_or_else(|| "No root modules specified".to_string())
+}
+
+async fn call_deno_info_single(
+    deno_executable: &str,
+    dir: &Path,
     specifier: &ModuleSpecifier,
 ) -> Result<DenoInfo, String> {
     let output = tokio::process::Command::new(deno_executable)
@@ -137,7 +282,12 @@ pub async fn call_deno_info(
         ));
     }
 
-    let info: Result<DenoInfo, _> = serde_json::from_slice(&output.stdout);
+    parse_deno_info(&output.stdout)
+}
+
+/// Parses the output of `deno info --json <specifier>`
+pub fn parse_deno_info(json: &[u8]) -> Result<DenoInfo, String> {
+    let info: Result<DenoInfo, _> = serde_json::from_slice(json);
 
     match info {
         Ok(info) => {
@@ -151,14 +301,13 @@ pub async fn call_deno_info(
             }
         }
         Err(e) => {
-            let preflight: Result<PreflightInfo, _> = serde_json::from_slice(&output.stdout);
+            let preflight: Result<PreflightInfo, _> = serde_json::from_slice(json);
 
             let diagnostics: String = if let Ok(preflight_info) = preflight {
                 preflight_info
                     .modules
                     .iter()
-                    .map(|m| m.diagnostics())
-                    .flatten()
+                    .flat_map(|m| m.diagnostics())
                     .collect::<Vec<_>>()
                     .join("\n")
                     .to_string()
@@ -166,7 +315,7 @@ pub async fn call_deno_info(
                 String::new()
             };
 
-            if diagnostics.len() > 0 {
+            if !diagnostics.is_empty() {
                 Err(format!(
                     "Failed to parse deno info output:\n{}\nOriginal error:\n{}",
                     diagnostics, e,
@@ -177,3 +326,104 @@ pub async fn call_deno_info(
         }
     }
 }
+
+/// Output of `deno info --json` without a specifier
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DenoDirs {
+    pub deno_dir: PathBuf,
+    pub npm_cache: PathBuf,
+}
+
+pub async fn call_deno_dirs(
+    deno_executable: &str,
+    dir: impl AsRef<Path>,
+) -> Result<DenoDirs, String> {
+    let output = tokio::process::Command::new(deno_executable)
+        .current_dir(dir)
+        .arg("info")
+        .arg("--json")
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute deno info: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Deno info command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Failed to parse deno info output: {}", e))
+}
+
+#[test]
+fn test_prune() {
+    let mut info: DenoInfo = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "roots": ["file:///main.ts"],
+        "modules": [
+            {
+                "kind": "esm",
+                "specifier": "file:///main.ts",
+                "local": "/main.ts",
+                "mediaType": "TypeScript",
+                "dependencies": [
+                    { "specifier": "./a.ts", "code": { "specifier": "file:///a.ts" } },
+                    { "specifier": "npm:dep", "code": { "specifier": "npm:dep" } }
+                ]
+            },
+            { "kind": "esm", "specifier": "file:///a.ts", "local": "/a.ts", "mediaType": "TypeScript" },
+            // no longer imported by main.ts
+            { "kind": "esm", "specifier": "file:///b.ts", "local": "/b.ts", "mediaType": "TypeScript" },
+            { "kind": "npm", "specifier": "npm:/dep@1.0.0", "npmPackage": "dep@1.0.0" },
+            { "kind": "npm", "specifier": "npm:/old@1.0.0", "npmPackage": "old@1.0.0" }
+        ],
+        "npmPackages": {
+            "dep@1.0.0": {
+                "name": "dep",
+                "version": "1.0.0",
+                "dependencies": ["inner@1.0.0"],
+                "registryUrl": "https://registry.npmjs.org/"
+            },
+            "inner@1.0.0": {
+                "name": "inner",
+                "version": "1.0.0",
+                "dependencies": [],
+                "registryUrl": "https://registry.npmjs.org/"
+            },
+            "old@1.0.0": {
+                "name": "old",
+                "version": "1.0.0",
+                "dependencies": [],
+                "registryUrl": "https://registry.npmjs.org/"
+            }
+        },
+        "packages": {},
+        "redirects": {
+            "npm:dep": "npm:/dep@1.0.0",
+            "npm:old": "npm:/old@1.0.0"
+        }
+    }))
+    .unwrap();
+
+    info.prune();
+
+    let mut modules = info
+        .modules
+        .iter()
+        .map(|m| m.specifier().to_string())
+        .collect::<Vec<_>>();
+    modules.sort();
+    assert_eq!(
+        modules,
+        ["file:///a.ts", "file:///main.ts", "npm:/dep@1.0.0"]
+    );
+
+    let mut packages = info.npm_packages.keys().cloned().collect::<Vec<_>>();
+    packages.sort();
+    assert_eq!(packages, ["dep@1.0.0", "inner@1.0.0"]);
+
+    assert_eq!(info.redirects.len(), 1);
+}
@@ -63,14 +63,7 @@ impl PreflightModuleDependency {
     }
 
     pub fn diagnostics(&self) -> Option<String> {
-        if let Some(code) = &self.code {
-            if let Some(error) = &code.error {
-                Some(format!("Dependency {}:\n{}", self.specifier, error))
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        let error = self.code.as_ref()?.error.as_ref()?;
+        Some(format!("Dependency {}:\n{}", self.specifier, error))
     }
 }
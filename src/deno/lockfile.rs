@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{jsr::id::JsrPackageId, specifier::ModuleSpecifier};
+
+/// The parts of `deno.lock` (v4) needed to verify served content
+#[derive(Debug, Default, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub version: String,
+    /// Package requirement (`jsr:@std/path@^1.0.8`) -> resolved version (`1.0.8`)
+    #[serde(default)]
+    pub specifiers: HashMap<String, String>,
+    /// Remote module URL -> sha256 (hex)
+    #[serde(default)]
+    pub remote: HashMap<String, String>,
+    /// Remote module URL -> redirect target
+    #[serde(default)]
+    pub redirects: HashMap<String, String>,
+    /// npm package id -> tarball integrity
+    #[serde(default)]
+    pub npm: HashMap<String, NpmLockEntry>,
+    /// jsr package id -> integrity of the version manifest
+    #[serde(default)]
+    pub jsr: HashMap<String, JsrLockEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NpmLockEntry {
+    pub integrity: Option<String>,
+    /// `name` if only one version of the package is locked, `name@version` otherwise
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JsrLockEntry {
+    pub integrity: String,
+}
+
+/// `<name>/<version>_meta.json` of a jsr package, its sha256 is locked in the `jsr` section
+#[derive(Debug, Deserialize)]
+pub struct JsrManifest {
+    /// Path in the package (`/mod.ts`) -> file
+    pub manifest: HashMap<String, JsrManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JsrManifestEntry {
+    /// `sha256-<hex>`
+    pub checksum: String,
+}
+
+#[derive(Debug)]
+pub enum IntegrityError {
+    Mismatch(Box<Mismatch>),
+    /// The lockfile has an entry, but the content can't be checked against it
+    Unverifiable {
+        specifier: ModuleSpecifier,
+        reason: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct Mismatch {
+    pub specifier: ModuleSpecifier,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl IntegrityError {
+    fn mismatch(specifier: ModuleSpecifier, expected: String, actual: String) -> Self {
+        IntegrityError::Mismatch(Box::new(Mismatch {
+            specifier,
+            expected,
+            actual,
+        }))
+    }
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityError::Mismatch(mismatch) => write!(
+                f,
+                "Integrity check failed for {}: lockfile has {}, content on disk has {}",
+                mismatch.specifier, mismatch.expected, mismatch.actual
+            ),
+            IntegrityError::Unverifiable { specifier, reason } => {
+                write!(f, "Integrity check failed for {}: {}", specifier, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+impl Lockfile {
+    pub async fn load(path: &Path) -> Result<Lockfile, String> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let lockfile: Lockfile = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        if !matches!(lockfile.version.as_str(), "4" | "5") {
+            warn!(target: "graph",
+                "Lockfile version {} is not supported, integrity checks may be incomplete",
+                lockfile.version
+            );
+        }
+
+        Ok(lockfile)
+    }
+
+    /// Verifies remote module content against the `remote` section.
+    ///
+    /// Local modules and remote modules without a lockfile entry pass. jsr modules are not listed
+    /// individually, see `verify_jsr_module`.
+    pub fn verify_module(
+        &self,
+        specifier: &ModuleSpecifier,
+        content: &[u8],
+    ) -> Result<(), IntegrityError> {
+        if !matches!(specifier.scheme(), "http" | "https") {
+            return Ok(());
+        }
+
+        let Some(expected) = self.remote.get(specifier.as_str()) else {
+            return Ok(());
+        };
+
+        let actual = sha256_hex(content);
+
+        if actual.eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(IntegrityError::mismatch(
+                specifier.clone(),
+                expected.clone(),
+                actual,
+            ))
+        }
+    }
+
+    /// Verifies a jsr module against the file checksums of its package manifest and the manifest against
+    /// the `jsr` section. Modules of packages without a lockfile entry pass.
+    pub async fn verify_jsr_module(
+        &self,
+        specifier: &ModuleSpecifier,
+        local: &Path,
+        content: &[u8],
+    ) -> Result<(), IntegrityError> {
+        let Some((id, path)) = JsrPackageId::from_url(specifier) else {
+            return Ok(());
+        };
+        let Some(entry) = self.jsr.get(&id.to_string()) else {
+            return Ok(());
+        };
+
+        let unverifiable = |reason: String| IntegrityError::Unverifiable {
+            specifier: specifier.clone(),
+            reason,
+        };
+
+        let manifest_path = jsr_manifest_path(specifier, local)
+            .ok_or_else(|| unverifiable("package manifest not found".to_string()))?;
+        let manifest = tokio::fs::read(&manifest_path).await.map_err(|e| {
+            unverifiable(format!(
+                "Failed to read the package manifest {}: {}",
+                manifest_path.display(),
+                e
+            ))
+        })?;
+
+        let actual = sha256_hex(&manifest);
+        if !actual.eq_ignore_ascii_case(&entry.integrity) {
+            return Err(IntegrityError::mismatch(
+                jsr_manifest_url(&id),
+                entry.integrity.clone(),
+                actual,
+            ));
+        }
+
+        let manifest: JsrManifest = serde_json::from_slice(&manifest)
+            .map_err(|e| unverifiable(format!("Invalid package manifest: {}", e)))?;
+        let file = manifest
+            .manifest
+            .get(&format!("/{}", path))
+            .ok_or_else(|| unverifiable("not part of the package manifest".to_string()))?;
+
+        let actual = format!("sha256-{}", sha256_hex(content));
+        if actual.eq_ignore_ascii_case(&file.checksum) {
+            Ok(())
+        } else {
+            Err(IntegrityError::mismatch(
+                specifier.clone(),
+                file.checksum.clone(),
+                actual,
+            ))
+        }
+    }
+
+    /// Tarball integrity (`sha512-...`) of a npm package (`name@version`)
+    pub fn npm_integrity(&self, id: &str) -> Option<&str> {
+        self.npm
+            .get(id)
+            .and_then(|entry| entry.integrity.as_deref())
+    }
+
+    /// Metadata consistency check of a npm package: the integrity the npm cache recorded for it
+    /// (`registry.json`) has to match the `npm` section. The extracted files are not hashed, deno discards
+    /// the tarball after checking it, so they are served (`/@npm-data`) as deno extracted them.
+    /// Packages without a locked integrity pass.
+    pub fn verify_npm_metadata(
+        &self,
+        id: &str,
+        registry_integrity: Option<&str>,
+    ) -> Result<(), IntegrityError> {
+        let Some(expected) = self.npm_integrity(id) else {
+            return Ok(());
+        };
+
+        let specifier = ModuleSpecifier::parse(&format!("npm:{}", id))
+            .unwrap_or_else(|_| ModuleSpecifier::parse("npm:unknown").unwrap());
+
+        match registry_integrity {
+            Some(actual) if actual == expected => Ok(()),
+            Some(actual) => Err(IntegrityError::mismatch(
+                specifier,
+                expected.to_string(),
+                actual.to_string(),
+            )),
+            None => Err(IntegrityError::Unverifiable {
+                specifier,
+                reason: "integrity not found in the registry metadata of the npm cache".to_string(),
+            }),
+        }
+    }
+}
+
+fn jsr_manifest_url(id: &JsrPackageId) -> ModuleSpecifier {
+    ModuleSpecifier::parse(&format!(
+        "https://jsr.io/{}/{}_meta.json",
+        id.name, id.version
+    ))
+    .unwrap()
+}
+
+/// `_meta.json` of the package of a jsr module. Vendored: next to the package directory, deno cache: in the
+/// same directory as the module (file names are hashes of the URL path).
+pub fn jsr_manifest_path(specifier: &ModuleSpecifier, local: &Path) -> Option<PathBuf> {
+    let (id, path) = JsrPackageId::from_url(specifier)?;
+
+    if local.ends_with(&path) {
+        let package_dir = local.ancestors().nth(path.split('/').count())?;
+        Some(package_dir.with_file_name(format!("{}_meta.json", id.version)))
+    } else {
+        let url = jsr_manifest_url(&id);
+        Some(local.parent()?.join(sha256_hex(url.path().as_bytes())))
+    }
+}
+
+pub(crate) fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[test]
+fn test_verify_module() {
+    let specifier = ModuleSpecifier::parse("https://example.com/mod.ts").unwrap();
+    let lockfile = Lockfile {
+        remote: HashMap::from([(
+            specifier.to_string(),
+            // sha256("hello")
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string(),
+        )]),
+        ..Default::default()
+    };
+
+    assert!(lockfile.verify_module(&specifier, b"hello").is_ok());
+    assert!(lockfile.verify_module(&specifier, b"tampered").is_err());
+}
+
+#[test]
+fn test_verify_npm_metadata() {
+    let lockfile = Lockfile {
+        npm: HashMap::from([(
+            "preact@10.26.5".to_string(),
+            NpmLockEntry {
+                integrity: Some("sha512-locked".to_string()),
+                dependencies: Vec::new(),
+            },
+        )]),
+        ..Default::default()
+    };
+
+    assert!(
+        lockfile
+            .verify_npm_metadata("preact@10.26.5", Some("sha512-locked"))
+            .is_ok()
+    );
+    // the cache recorded a different tarball than the locked one
+    assert!(
+        lockfile
+            .verify_npm_metadata("preact@10.26.5", Some("sha512-tampered"))
+            .is_err()
+    );
+    assert!(
+        lockfile
+            .verify_npm_metadata("preact@10.26.5", None)
+            .is_err()
+    );
+    assert!(lockfile.verify_npm_metadata("other@1.0.0", None).is_ok());
+}
+
+#[tokio::test]
+async fn test_verify_jsr_module() {
+    let dir = std::env::temp_dir().join(format!("deno-plc-build-jsr-{}", std::process::id()));
+    let package_dir = dir.join("remote/jsr.io/@std/path/1.0.8");
+    std::fs::create_dir_all(&package_dir).unwrap();
+
+    // sha256("hello")
+    let manifest = r#"{"manifest":{"/mod.ts":{"size":5,"checksum":"sha256-2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"}}}"#;
+    std::fs::write(
+        dir.join("remote/jsr.io/@std/path/1.0.8_meta.json"),
+        manifest,
+    )
+    .unwrap();
+
+    let lockfile = Lockfile {
+        jsr: HashMap::from([(
+            "@std/path@1.0.8".to_string(),
+            JsrLockEntry {
+                integrity: sha256_hex(manifest.as_bytes()),
+            },
+        )]),
+        ..Default::default()
+    };
+
+    let specifier = ModuleSpecifier::parse("https://jsr.io/@std/path/1.0.8/mod.ts").unwrap();
+    let local = package_dir.join("mod.ts");
+
+    assert!(
+        lockfile
+            .verify_jsr_module(&specifier, &local, b"hello")
+            .await
+            .is_ok()
+    );
+    assert!(
+        lockfile
+            .verify_jsr_module(&specifier, &local, b"tampered")
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(dir).ok();
+}
@@ -4,11 +4,46 @@ use crate::specifier::MediaType as DenoMediaType;
 use serde::{Deserialize, Serialize};
 
 #[repr(transparent)]
-#[derive(Debug, Serialize, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct MediaType {
     pub media_type: DenoMediaType,
 }
 
+impl MediaType {
+    /// Name as used by `deno info`
+    pub fn as_str(&self) -> &'static str {
+        match self.media_type {
+            DenoMediaType::JavaScript => "JavaScript",
+            DenoMediaType::Jsx => "JSX",
+            DenoMediaType::Mjs => "Mjs",
+            DenoMediaType::Cjs => "Cjs",
+            DenoMediaType::TypeScript => "TypeScript",
+            DenoMediaType::Mts => "Mts",
+            DenoMediaType::Cts => "Cts",
+            DenoMediaType::Dts => "Dts",
+            DenoMediaType::Dmts => "Dmts",
+            DenoMediaType::Dcts => "Dcts",
+            DenoMediaType::Tsx => "TSX",
+            DenoMediaType::Css => "Css",
+            DenoMediaType::Json => "Json",
+            DenoMediaType::Html => "Html",
+            DenoMediaType::Sql => "Sql",
+            DenoMediaType::Wasm => "Wasm",
+            DenoMediaType::SourceMap => "SourceMap",
+            DenoMediaType::Unknown => "Unknown",
+        }
+    }
+}
+
+impl Serialize for MediaType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 impl<'de> Deserialize<'de> for MediaType {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
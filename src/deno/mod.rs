@@ -1,3 +1,7 @@
 pub mod info;
 pub mod info_preflight;
+pub mod lockfile;
 pub mod media_type;
+#[cfg(feature = "native")]
+pub mod native;
+pub mod workspace;
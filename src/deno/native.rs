@@ -0,0 +1,859 @@
+//! Builds the graph with `deno_graph` instead of spawning `deno info`.
+//!
+//! Remote modules are read from the deno http cache (`$DENO_DIR/remote`), npm packages and jsr
+//! versions are taken from the lockfile. Imports are resolved with the import map of the `deno.json`.
+//! Nothing is downloaded, run `deno cache` (or `deno install`) first.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use deno_graph::{
+    BuildOptions, FillFromLockfileOptions, GraphKind, ModuleGraph, Range,
+    source::{
+        LoadFuture, LoadOptions, LoadResponse, Loader, ResolutionKind, ResolveError, Resolver,
+    },
+};
+use deno_semver::jsr::JsrDepPackageReq;
+use import_map::ImportMap;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use url::Url;
+
+use crate::{config::user_cache_dir, npm::id::NPMPackageId, specifier::ModuleSpecifier};
+
+use super::{
+    info::{
+        BuiltInNodeModule, DenoInfo, EsmDependency, EsmDependencyCode, EsmModule, ExternalModule,
+        Module, NpmModule, NpmPackage,
+    },
+    lockfile::Lockfile,
+    workspace::strip_json_comments,
+};
+
+const METADATA_SEPARATOR: &[u8] = b"\n// denoCacheMetadata=";
+
+/// Resolves the deno cache directory like the deno CLI does
+pub fn default_deno_dir() -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var("DENO_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    user_cache_dir().map(|cache| cache.join("deno"))
+}
+
+/// Produces the same `DenoInfo` as `deno info --json` for the given roots. The `deno.json` (or
+/// `deno.jsonc`) of `config_dir` is used like by the deno CLI.
+pub async fn build_native_info<'a>(
+    deno_dir: &Path,
+    config_dir: &Path,
+    roots: impl IntoIterator<Item = &'a ModuleSpecifier>,
+    lockfile: Option<&Lockfile>,
+) -> Result<DenoInfo, String> {
+    let roots = roots.into_iter().cloned().collect::<Vec<_>>();
+
+    if roots.is_empty() {
+        return Err("No root modules specified".to_string());
+    }
+
+    let loader = CacheLoader::new(deno_dir.join("remote"));
+    let resolver = ConfigResolver::from_config_dir(config_dir).await?;
+
+    let empty_lockfile = Lockfile::default();
+    let lockfile = lockfile.unwrap_or(&empty_lockfile);
+
+    // `deno info` follows type only imports too
+    let mut graph = ModuleGraph::new(GraphKind::All);
+
+    // jsr requirements resolve to the locked versions instead of the latest cached one
+    let package_specifiers = lockfile
+        .specifiers
+        .iter()
+        .filter_map(|(requirement, version)| {
+            Some((
+                JsrDepPackageReq::from_str(requirement).ok()?,
+                version.as_str(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    graph.fill_from_lockfile(FillFromLockfileOptions {
+        redirects: lockfile
+            .redirects
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str())),
+        package_specifiers: package_specifiers
+            .iter()
+            .map(|(requirement, version)| (requirement, *version)),
+    });
+
+    graph
+        .build(
+            roots.clone(),
+            &loader,
+            BuildOptions {
+                resolver: Some(&resolver),
+                ..Default::default()
+            },
+        )
+        .await;
+
+    if let Err(err) = graph.valid() {
+        warn!(target: "graph", "Module graph contains errors: {}", err);
+    }
+
+    let locals = std::mem::take(&mut *loader.locals.lock().unwrap());
+
+    let mut modules = Vec::new();
+    let mut redirects = std::mem::take(&mut graph.redirects)
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let mut npm_modules = HashSet::new();
+
+    for module in graph.modules() {
+        match module {
+            deno_graph::Module::Js(js) => {
+                let Some((local, size)) = locals.get(&js.specifier).cloned() else {
+                    continue;
+                };
+                let media_type = serde_json::to_value(js.media_type)
+                    .and_then(serde_json::from_value)
+                    .map_err(|e| format!("Unsupported media type of {}: {}", js.specifier, e))?;
+
+                modules.push(Module::Esm(EsmModule {
+                    specifier: js.specifier.clone(),
+                    media_type,
+                    local,
+                    size,
+                    dependencies: js
+                        .dependencies
+                        .iter()
+                        .map(|(specifier, dep)| EsmDependency {
+                            specifier: specifier.clone(),
+                            code: dep.get_code().map(|specifier| EsmDependencyCode {
+                                specifier: specifier.clone(),
+                            }),
+                            is_dynamic: dep.is_dynamic,
+                        })
+                        .collect(),
+                }));
+            }
+            deno_graph::Module::Npm(npm) => modules.push(Module::Npm(NpmModule {
+                specifier: npm.specifier.clone(),
+                npm_package: npm.nv_reference.nv().to_string(),
+            })),
+            deno_graph::Module::Node(node) => modules.push(Module::Node(BuiltInNodeModule {
+                specifier: node.specifier.clone(),
+                module_name: node.module_name.to_string(),
+            })),
+            deno_graph::Module::External(external) => {
+                if external.specifier.scheme() == "npm" {
+                    // like deno, the module is listed once under the locked version
+                    match pin_npm_specifier(&external.specifier, lockfile) {
+                        Some((specifier, npm_package)) => {
+                            if specifier != external.specifier {
+                                redirects.insert(external.specifier.clone(), specifier.clone());
+                            }
+                            if npm_modules.insert(specifier.clone()) {
+                                modules.push(Module::Npm(NpmModule {
+                                    specifier,
+                                    npm_package,
+                                }));
+                            }
+                        }
+                        None => warn!(target: "npm",
+                            "npm package {} is not part of the lockfile",
+                            external.specifier
+                        ),
+                    }
+                } else if external.specifier.scheme() == "node" {
+                    modules.push(Module::Node(BuiltInNodeModule {
+                        specifier: external.specifier.clone(),
+                        module_name: external.specifier.path().to_string(),
+                    }));
+                } else {
+                    modules.push(Module::External(ExternalModule {
+                        specifier: external.specifier.clone(),
+                    }));
+                }
+            }
+            // not part of the `deno info` graph either
+            deno_graph::Module::Json(_) | deno_graph::Module::Wasm(_) => {}
+        }
+    }
+
+    let packages = lockfile
+        .specifiers
+        .iter()
+        .filter_map(|(requirement, version)| {
+            let requirement = requirement.strip_prefix("jsr:")?;
+            let name_end = requirement[1..].find('@')? + 1;
+            Some((
+                requirement.to_string(),
+                format!("{}@{}", &requirement[..name_end], version),
+            ))
+        })
+        .collect();
+
+    Ok(DenoInfo::new(
+        roots,
+        modules,
+        npm_packages(lockfile),
+        packages,
+        redirects,
+    ))
+}
+
+/// Resolves imports with the import map of a `deno.json` (`imports` and `scopes`, or the file
+/// referenced by `importMap`) and provides its `compilerOptions.jsxImportSource`
+#[derive(Debug, Default)]
+pub struct ConfigResolver {
+    import_map: Option<ImportMap>,
+    jsx_import_source: Option<String>,
+}
+
+impl ConfigResolver {
+    /// Reads the `deno.json` or `deno.jsonc` in `dir`, imports are resolved as URLs without one
+    pub async fn from_config_dir(dir: &Path) -> Result<Self, String> {
+        for file in ["deno.json", "deno.jsonc"] {
+            let path = dir.join(file);
+            let Ok(content) = tokio::fs::read_to_string(&path).await else {
+                continue;
+            };
+            let config: serde_json::Value = serde_json::from_str(&strip_json_comments(&content))
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+            return Self::from_config(&path, &config).await;
+        }
+
+        Ok(Self::default())
+    }
+
+    async fn from_config(path: &Path, config: &serde_json::Value) -> Result<Self, String> {
+        let config_url = Url::from_file_path(path)
+            .map_err(|_| format!("Invalid config path {}", path.display()))?;
+
+        let import_map = match config["importMap"].as_str() {
+            Some(import_map) => {
+                let url = config_url
+                    .join(import_map)
+                    .map_err(|e| format!("Invalid import map {}: {}", import_map, e))?;
+                let path = url
+                    .to_file_path()
+                    .map_err(|_| format!("Import map {} is not a local file", url))?;
+                let content = tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                Some(
+                    import_map::parse_from_json(url, &content)
+                        .map_err(|e| format!("Invalid import map {}: {}", path.display(), e))?
+                        .import_map,
+                )
+            }
+            None if config.get("imports").is_some() || config.get("scopes").is_some() => {
+                let value = serde_json::json!({
+                    "imports": config.get("imports").cloned().unwrap_or_else(|| serde_json::json!({})),
+                    "scopes": config.get("scopes").cloned().unwrap_or_else(|| serde_json::json!({})),
+                });
+                // `"@std/path": "jsr:@std/path@^1"` also maps `@std/path/` like in the deno CLI
+                let value = import_map::ext::expand_import_map_value(value);
+                Some(
+                    import_map::parse_from_value(config_url, value)
+                        .map_err(|e| format!("Invalid imports in {}: {}", path.display(), e))?
+                        .import_map,
+                )
+            }
+            None => None,
+        };
+
+        let compiler_options = &config["compilerOptions"];
+        // the import source only applies to the automatic runtime
+        let jsx_import_source = matches!(
+            compiler_options["jsx"].as_str(),
+            Some("react-jsx" | "react-jsxdev" | "precompile")
+        )
+        .then(|| compiler_options["jsxImportSource"].as_str())
+        .flatten()
+        .map(|source| source.to_string());
+
+        Ok(Self {
+            import_map,
+            jsx_import_source,
+        })
+    }
+}
+
+impl Resolver for ConfigResolver {
+    fn default_jsx_import_source(&self, _referrer: &ModuleSpecifier) -> Option<String> {
+        self.jsx_import_source.clone()
+    }
+
+    fn resolve(
+        &self,
+        specifier_text: &str,
+        referrer_range: &Range,
+        _kind: ResolutionKind,
+    ) -> Result<ModuleSpecifier, ResolveError> {
+        match &self.import_map {
+            Some(import_map) => Ok(import_map.resolve(specifier_text, &referrer_range.specifier)?),
+            None => Ok(deno_graph::resolve_import(
+                specifier_text,
+                &referrer_range.specifier,
+            )?),
+        }
+    }
+}
+
+/// `npm:/preact@^10.25.4/jsx-runtime` -> (`npm:/preact@10.25.4/jsx-runtime`, `preact@10.25.4`).
+/// The package id keeps the peer dependency suffix of the lockfile (`2.0.1_preact@10.25.4`).
+fn pin_npm_specifier(
+    specifier: &ModuleSpecifier,
+    lockfile: &Lockfile,
+) -> Option<(ModuleSpecifier, String)> {
+    let (name, requirement, subpath) = split_npm_specifier(specifier)?;
+
+    let version = lockfile
+        .specifiers
+        .get(&format!("npm:{}@{}", name, requirement))
+        .or_else(|| lockfile.specifiers.get(&format!("npm:{}", name)))?;
+    let plain_version = version.split('_').next().unwrap_or_default();
+
+    let pinned = match subpath {
+        Some(subpath) => format!("npm:/{}@{}/{}", name, plain_version, subpath),
+        None => format!("npm:/{}@{}", name, plain_version),
+    };
+
+    Some((
+        ModuleSpecifier::parse(&pinned).ok()?,
+        format!("{}@{}", name, version),
+    ))
+}
+
+/// `npm:/@scope/name@^1.0.0/sub` -> (`@scope/name`, `^1.0.0`, `sub`)
+fn split_npm_specifier(specifier: &ModuleSpecifier) -> Option<(&str, &str, Option<&str>)> {
+    let rest = specifier
+        .as_str()
+        .strip_prefix("npm:")?
+        .trim_start_matches('/');
+    let name_end = rest[1..]
+        .find(['@', '/'])
+        .map(|i| i + 1)
+        .unwrap_or(rest.len());
+    // scoped packages contain a slash
+    let name_end = if rest.starts_with('@') {
+        rest[name_end + 1..]
+            .find(['@', '/'])
+            .map(|i| i + name_end + 1)
+            .unwrap_or(rest.len())
+    } else {
+        name_end
+    };
+    let name = &rest[..name_end];
+
+    let (requirement, subpath) = match rest[name_end..].strip_prefix('@') {
+        Some(rest) => match rest.split_once('/') {
+            Some((requirement, subpath)) => (requirement, Some(subpath)),
+            None => (rest, None),
+        },
+        None => ("", rest[name_end..].strip_prefix('/')),
+    };
+
+    Some((
+        name,
+        requirement,
+        subpath.filter(|subpath| !subpath.is_empty()),
+    ))
+}
+
+fn npm_packages(lockfile: &Lockfile) -> HashMap<String, NpmPackage> {
+    let registry_url = Url::parse("https://registry.npmjs.org/").unwrap();
+
+    // packages locked in a single version are referenced by name only
+    let mut by_name: HashMap<String, Vec<&str>> = HashMap::new();
+    for id in lockfile.npm.keys() {
+        if let Some(package) = NPMPackageId::from_string(id) {
+            by_name.entry(package.name).or_default().push(id);
+        }
+    }
+
+    lockfile
+        .npm
+        .iter()
+        .filter_map(|(id, entry)| {
+            let package = NPMPackageId::from_string(id)?;
+            let dependencies = entry
+                .dependencies
+                .iter()
+                .filter_map(|dep| match NPMPackageId::from_string(dep) {
+                    Some(_) => Some(dep.clone()),
+                    None => by_name
+                        .get(dep)
+                        .and_then(|ids| ids.first())
+                        .map(|id| id.to_string()),
+                })
+                .collect();
+
+            Some((
+                id.clone(),
+                NpmPackage {
+                    name: package.name,
+                    // strip peer dependency suffix (`1.0.0_react@18.0.0`)
+                    version: package
+                        .version
+                        .split('_')
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    dependencies,
+                    registry_url: registry_url.clone(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Loads local files from disk and remote modules from the deno http cache
+struct CacheLoader {
+    remote_dir: PathBuf,
+    /// Loaded module -> (file on disk, size of the content), shared with the load futures
+    locals: Arc<Mutex<HashMap<ModuleSpecifier, (PathBuf, u64)>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CacheMetadata {
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+impl CacheLoader {
+    fn new(remote_dir: PathBuf) -> Self {
+        Self {
+            remote_dir,
+            locals: Default::default(),
+        }
+    }
+
+    /// `$DENO_DIR/remote/<scheme>/<host>[_PORT<port>]/<sha256 of path and query>`
+    fn cache_path(&self, specifier: &ModuleSpecifier) -> Option<PathBuf> {
+        let host = match specifier.port() {
+            Some(port) => format!("{}_PORT{}", specifier.host_str()?, port),
+            None => specifier.host_str()?.to_string(),
+        };
+
+        let mut rest = specifier.path().to_string();
+        if let Some(query) = specifier.query() {
+            rest.push('?');
+            rest.push_str(query);
+        }
+
+        let hash = Sha256::digest(rest.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        Some(
+            self.remote_dir
+                .join(specifier.scheme())
+                .join(host)
+                .join(hash),
+        )
+    }
+}
+
+impl Loader for CacheLoader {
+    fn load(&self, specifier: &ModuleSpecifier, _options: LoadOptions) -> LoadFuture {
+        let specifier = specifier.clone();
+
+        let path = match specifier.scheme() {
+            "file" => specifier.to_file_path().ok(),
+            "http" | "https" => self.cache_path(&specifier),
+            "npm" | "node" => {
+                return Box::pin(async move { Ok(Some(LoadResponse::External { specifier })) });
+            }
+            _ => None,
+        };
+
+        let Some(path) = path else {
+            return Box::pin(async { Ok(None) });
+        };
+
+        let locals = self.locals.clone();
+
+        Box::pin(async move {
+            let result = load_file(&specifier, &path).await;
+
+            if let Ok(Some(LoadResponse::Module { content, .. })) = &result {
+                locals
+                    .lock()
+                    .unwrap()
+                    .insert(specifier.clone(), (path, content.len() as u64));
+            }
+
+            result
+        })
+    }
+}
+
+async fn load_file(
+    specifier: &ModuleSpecifier,
+    path: &Path,
+) -> Result<Option<LoadResponse>, deno_graph::source::LoadError> {
+    let Ok(mut content) = tokio::fs::read(path).await else {
+        if specifier.scheme() != "file" {
+            warn!(target: "graph", "{} is not cached, run `deno cache` first", specifier);
+        }
+        return Ok(None);
+    };
+
+    let mut headers = None;
+
+    if specifier.scheme() != "file" {
+        let metadata = match find_subslice(&content, METADATA_SEPARATOR) {
+            Some(pos) => {
+                let metadata = serde_json::from_slice::<CacheMetadata>(
+                    &content[pos + METADATA_SEPARATOR.len()..],
+                )
+                .ok();
+                content.truncate(pos);
+                metadata
+            }
+            // cache format of older deno versions
+            None => tokio::fs::read(path.with_extension("metadata.json"))
+                .await
+                .ok()
+                .and_then(|m| serde_json::from_slice::<CacheMetadata>(&m).ok()),
+        };
+
+        if let Some(metadata) = metadata {
+            if let Some(location) = metadata.headers.get("location")
+                && let Ok(redirect) = specifier.join(location)
+            {
+                return Ok(Some(LoadResponse::Redirect {
+                    specifier: redirect,
+                }));
+            }
+            headers = Some(metadata.headers);
+        }
+    }
+
+    Ok(Some(LoadResponse::Module {
+        content: Arc::from(content.into_boxed_slice()),
+        specifier: specifier.clone(),
+        maybe_headers: headers,
+    }))
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+#[test]
+fn test_pin_npm_specifier() {
+    let lockfile = Lockfile {
+        specifiers: HashMap::from([
+            ("npm:preact@^10.25.4".to_string(), "10.25.4".to_string()),
+            ("npm:@xterm/xterm@^5.5.0".to_string(), "5.5.0".to_string()),
+        ]),
+        ..Default::default()
+    };
+
+    let pin = |specifier: &str| {
+        pin_npm_specifier(&ModuleSpecifier::parse(specifier).unwrap(), &lockfile)
+            .map(|(specifier, package)| (specifier.to_string(), package))
+    };
+
+    assert_eq!(
+        pin("npm:/preact@^10.25.4/jsx-runtime"),
+        Some((
+            "npm:/preact@10.25.4/jsx-runtime".to_string(),
+            "preact@10.25.4".to_string()
+        ))
+    );
+    assert_eq!(
+        pin("npm:@xterm/xterm@^5.5.0"),
+        Some((
+            "npm:/@xterm/xterm@5.5.0".to_string(),
+            "@xterm/xterm@5.5.0".to_string()
+        ))
+    );
+    assert_eq!(pin("npm:left-pad@^1.0.0"), None);
+}
+
+#[tokio::test]
+async fn test_config_resolver() {
+    let dir = std::env::temp_dir().join(format!("deno-plc-build-resolver-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("deno.jsonc"),
+        r#"{
+            // comments are allowed
+            "importMap": "./import_map.json",
+            "compilerOptions": { "jsx": "precompile", "jsxImportSource": "preact" }
+        }"#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("import_map.json"),
+        r#"{
+            "imports": { "preact": "npm:preact@^10.25.4", "preact/": "npm:/preact@^10.25.4/" },
+            "scopes": { "./vendor/": { "preact": "./vendor/preact.js" } }
+        }"#,
+    )
+    .unwrap();
+
+    let resolver = ConfigResolver::from_config_dir(&dir).await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let base = Url::from_directory_path(&dir).unwrap();
+    let resolve = |specifier: &str, referrer: &str| {
+        let range = Range {
+            specifier: base.join(referrer).unwrap(),
+            range: deno_graph::PositionRange::zeroed(),
+            resolution_mode: None,
+        };
+        resolver
+            .resolve(specifier, &range, ResolutionKind::Execution)
+            .unwrap()
+            .to_string()
+    };
+
+    assert_eq!(resolve("preact", "main.tsx"), "npm:preact@^10.25.4");
+    assert_eq!(
+        resolve("preact/hooks", "main.tsx"),
+        "npm:/preact@^10.25.4/hooks"
+    );
+    assert_eq!(
+        resolve("preact", "vendor/mod.ts"),
+        base.join("vendor/preact.js").unwrap().to_string()
+    );
+    assert_eq!(
+        resolve("./app.tsx", "main.tsx"),
+        base.join("app.tsx").unwrap().to_string()
+    );
+    assert_eq!(
+        resolver.default_jsx_import_source(&base).as_deref(),
+        Some("preact")
+    );
+}
+
+/// Media type or package, dependencies with code (specifier, resolved, dynamic)
+#[cfg(test)]
+type ModuleSummary = (String, Vec<(String, String, bool)>);
+
+/// Module -> summary
+#[cfg(test)]
+fn summarize_modules(info: &DenoInfo) -> std::collections::BTreeMap<String, ModuleSummary> {
+    info.modules
+        .iter()
+        .map(|module| {
+            let summary = match module {
+                Module::Esm(esm) => {
+                    let mut dependencies = esm
+                        .dependencies
+                        .iter()
+                        .filter_map(|dep| {
+                            Some((
+                                dep.specifier.clone(),
+                                dep.code.as_ref()?.specifier.to_string(),
+                                dep.is_dynamic,
+                            ))
+                        })
+                        .collect::<Vec<_>>();
+                    dependencies.sort();
+                    (format!("{:?}", esm.media_type), dependencies)
+                }
+                Module::Npm(npm) => (npm.npm_package.clone(), Vec::new()),
+                Module::Node(node) => (node.module_name.clone(), Vec::new()),
+                Module::External(_) => ("external".to_string(), Vec::new()),
+            };
+            (module.specifier().to_string(), summary)
+        })
+        .collect()
+}
+
+/// Rebuilds the project of `research/info.json` (sources reduced to their imports, the jsr
+/// packages in a deno cache, deno.json and lockfile) and compares the graph with `deno info`
+#[tokio::test]
+async fn test_research_info() {
+    use super::info::parse_deno_info;
+
+    let dir = std::env::temp_dir().join(format!("deno-plc-build-native-{}", std::process::id()));
+    let project = dir.join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    let project_url = Url::from_directory_path(&project).unwrap();
+
+    let json = std::fs::read_to_string(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("research/info.json"),
+    )
+    .unwrap()
+    .replace("file:///D:/dev/technik-app/", project_url.as_str());
+    let expected = parse_deno_info(json.as_bytes()).unwrap();
+    let raw = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+
+    let cache = CacheLoader::new(dir.join("deno/remote"));
+    let write = |path: PathBuf, content: &str| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+
+    let mut imports = serde_json::Map::new();
+
+    for module in raw["modules"].as_array().unwrap() {
+        if module["kind"] != "esm" {
+            continue;
+        }
+        let specifier = ModuleSpecifier::parse(module["specifier"].as_str().unwrap()).unwrap();
+
+        let mut pragma = String::new();
+        let mut source = String::new();
+        for dep in module["dependencies"].as_array().into_iter().flatten() {
+            let text = dep["specifier"].as_str().unwrap();
+            let Some(code) = dep["code"]["specifier"].as_str() else {
+                source.push_str(&format!("import type {{}} from \"{}\";\n", text));
+                continue;
+            };
+            let start = &dep["code"]["span"]["start"];
+            if start["line"] == 0 && dep["code"]["span"]["end"]["character"] == 0 {
+                // default jsx import source
+                continue;
+            }
+            if start["line"] == 0
+                && let Some(import_source) = text.strip_suffix("/jsx-runtime")
+            {
+                pragma = format!("/** @jsxImportSource {} */\n", import_source);
+                continue;
+            }
+
+            if dep["isDynamic"] == true {
+                source.push_str(&format!("await import(\"{}\");\n", text));
+            } else {
+                source.push_str(&format!("import \"{}\";\n", text));
+            }
+
+            // bare imports of the project come from the import map
+            if specifier.scheme() == "file"
+                && !text.starts_with('.')
+                && !text.starts_with('/')
+                && !text.contains(':')
+            {
+                let segments = if text.starts_with('@') { 2 } else { 1 };
+                let name = text
+                    .splitn(segments + 1, '/')
+                    .take(segments)
+                    .collect::<Vec<_>>();
+                let name = name.join("/");
+                let (key, target) = if code.starts_with("file:") {
+                    (text.to_string(), code.to_string())
+                } else {
+                    let target = code.strip_suffix(&text[name.len()..]).unwrap();
+                    (name, target.replacen(":/", ":", 1))
+                };
+                imports.insert(key, target.into());
+            }
+        }
+
+        let content = pragma + source.as_str();
+        match specifier.scheme() {
+            "file" => write(specifier.to_file_path().unwrap(), &content),
+            _ => write(cache.cache_path(&specifier).unwrap(), &content),
+        }
+    }
+
+    // jsr package exports, reconstructed from the resolved entrypoints
+    let mut exports = HashMap::<String, serde_json::Map<String, serde_json::Value>>::new();
+    for (from, to) in raw["redirects"].as_object().unwrap() {
+        let Some(requirement) = from.strip_prefix("jsr:") else {
+            continue;
+        };
+        let requirement = requirement.trim_start_matches('/');
+        let name_end = requirement[1..].find('@').unwrap() + 1;
+        let name = &requirement[..name_end];
+        let subpath = requirement[name_end..]
+            .split_once('/')
+            .map(|(_, subpath)| format!("./{}", subpath))
+            .unwrap_or_else(|| ".".to_string());
+
+        let rest = to
+            .as_str()
+            .unwrap()
+            .strip_prefix(&format!("https://jsr.io/{}/", name))
+            .unwrap();
+        let (version, path) = rest.split_once('/').unwrap();
+        exports
+            .entry(format!("{}/{}", name, version))
+            .or_default()
+            .insert(subpath, format!("./{}", path).into());
+    }
+    let mut versions = HashMap::<String, serde_json::Map<String, serde_json::Value>>::new();
+    for (package, exports) in exports {
+        let url = Url::parse(&format!("https://jsr.io/{}_meta.json", package)).unwrap();
+        let meta = serde_json::json!({ "exports": exports, "manifest": {} });
+        write(cache.cache_path(&url).unwrap(), &meta.to_string());
+
+        let (name, version) = package.rsplit_once('/').unwrap();
+        versions
+            .entry(name.to_string())
+            .or_default()
+            .insert(version.to_string(), serde_json::json!({}));
+    }
+    for (name, versions) in versions {
+        let url = Url::parse(&format!("https://jsr.io/{}/meta.json", name)).unwrap();
+        let meta = serde_json::json!({ "versions": versions });
+        write(cache.cache_path(&url).unwrap(), &meta.to_string());
+    }
+
+    let mut lockfile = Lockfile::default();
+    for (requirement, package) in raw["packages"].as_object().unwrap() {
+        let version = package.as_str().unwrap().rsplit_once('@').unwrap().1;
+        lockfile
+            .specifiers
+            .insert(format!("jsr:{}", requirement), version.to_string());
+    }
+    for (from, to) in &expected.redirects {
+        let (Some((name, requirement, _)), Some((_, version, _))) =
+            (split_npm_specifier(from), split_npm_specifier(to))
+        else {
+            continue;
+        };
+        let package = expected
+            .modules
+            .iter()
+            .find_map(|module| match module {
+                Module::Npm(npm)
+                    if npm.specifier.as_str() == format!("npm:/{}@{}", name, version) =>
+                {
+                    Some(npm.npm_package.clone())
+                }
+                _ => None,
+            })
+            .unwrap();
+        lockfile.specifiers.insert(
+            format!("npm:{}@{}", name, requirement),
+            package[name.len() + 1..].to_string(),
+        );
+    }
+
+    let config = serde_json::json!({
+        "imports": imports,
+        "compilerOptions": { "jsx": "react-jsx", "jsxImportSource": "preact" },
+    });
+    write(project.join("deno.json"), &config.to_string());
+
+    let info = build_native_info(
+        &dir.join("deno"),
+        &project,
+        &expected.roots,
+        Some(&lockfile),
+    )
+    .await
+    .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summarize_modules(&info), summarize_modules(&expected));
+    assert_eq!(info.packages, expected.packages);
+    assert_eq!(info.redirects, expected.redirects);
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use path_clean::PathClean;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use urlencoding::encode;
+
+const CONFIG_FILES: [&str; 3] = ["deno.json", "deno.jsonc", "package.json"];
+
+/// Deno workspace the project is part of
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Workspace {
+    /// Directory of the `deno.json` declaring the workspace
+    pub root: Option<PathBuf>,
+    pub members: Vec<WorkspaceMember>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceMember {
+    pub name: String,
+    pub path: PathBuf,
+    /// URL prefix the member is served under (`/@workspace/<name>`)
+    pub prefix: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigFile {
+    name: Option<String>,
+    #[serde(default)]
+    workspace: Option<WorkspaceField>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum WorkspaceField {
+    Members(Vec<String>),
+    Object { members: Vec<String> },
+}
+
+impl Workspace {
+    /// Searches `dir` and its ancestors for a `deno.json` with a `workspace` field
+    pub async fn discover(dir: &Path) -> Workspace {
+        for ancestor in dir.clean().ancestors() {
+            for file in &CONFIG_FILES[..2] {
+                let Some(config) = read_config(&ancestor.join(file)).await else {
+                    continue;
+                };
+
+                let members = match config.workspace {
+                    Some(WorkspaceField::Members(members))
+                    | Some(WorkspaceField::Object { members }) => members,
+                    None => continue,
+                };
+
+                return Workspace {
+                    root: Some(ancestor.to_path_buf()),
+                    members: resolve_members(ancestor, &members).await,
+                };
+            }
+        }
+
+        Workspace::default()
+    }
+
+    /// Finds the member containing `path` and returns the path relative to it
+    pub fn member_of(&self, path: &Path) -> Option<(&WorkspaceMember, PathBuf)> {
+        let path = path.clean();
+
+        self.members
+            .iter()
+            .filter_map(|member| {
+                path.strip_prefix(&member.path)
+                    .ok()
+                    .map(|relative| (member, relative.to_path_buf()))
+            })
+            // nested members: the most specific one wins
+            .max_by_key(|(member, _)| member.path.components().count())
+    }
+
+    pub fn member(&self, name: &str) -> Option<&WorkspaceMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+}
+
+async fn resolve_members(root: &Path, patterns: &[String]) -> Vec<WorkspaceMember> {
+    let mut dirs = Vec::new();
+
+    for pattern in patterns {
+        if pattern.contains(['?', '[', '{', '!']) {
+            warn!(target: "graph",
+                "Unsupported workspace member pattern {}, only * and ** are expanded",
+                pattern
+            );
+            continue;
+        }
+
+        if pattern.contains('*') {
+            let matched = expand_pattern(root, pattern).await;
+            if matched.is_empty() {
+                warn!(target: "graph", "Workspace member pattern {} matches no directory", pattern);
+            }
+            dirs.extend(matched);
+        } else {
+            dirs.push(root.join(pattern).clean());
+        }
+    }
+
+    let mut seen = HashSet::new();
+    dirs.retain(|dir| seen.insert(dir.clone()));
+
+    let mut members = Vec::new();
+
+    for dir in dirs {
+        let mut name = None;
+        let mut found = false;
+        for file in CONFIG_FILES {
+            if let Some(config) = read_config(&dir.join(file)).await {
+                found = true;
+                name = config.name;
+                if name.is_some() {
+                    break;
+                }
+            }
+        }
+
+        if !found {
+            continue;
+        }
+
+        let name = name.unwrap_or_else(|| {
+            dir.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        members.push(WorkspaceMember {
+            prefix: format!("/@workspace/{}", encode(&name)),
+            name,
+            path: dir,
+        });
+    }
+
+    members
+}
+
+/// Directories matching `pattern`, `*` matches within a path segment and `**` any number of directories
+/// (except `node_modules` and hidden ones)
+async fn expand_pattern(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+
+    for segment in pattern.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        let mut next = Vec::new();
+
+        for dir in dirs {
+            if segment == "**" {
+                let mut stack = vec![dir];
+                while let Some(dir) = stack.pop() {
+                    for sub_dir in sub_dirs(&dir).await {
+                        let name = sub_dir.file_name().unwrap_or_default().to_string_lossy();
+                        if name != "node_modules" && !name.starts_with('.') {
+                            stack.push(sub_dir);
+                        }
+                    }
+                    next.push(dir);
+                }
+            } else if segment.contains('*') {
+                for sub_dir in sub_dirs(&dir).await {
+                    let name = sub_dir.file_name().unwrap_or_default().to_string_lossy();
+                    if matches_segment(segment, &name) {
+                        next.push(sub_dir);
+                    }
+                }
+            } else {
+                let dir = dir.join(segment);
+                if dir.is_dir() {
+                    next.push(dir);
+                }
+            }
+        }
+
+        dirs = next;
+    }
+
+    dirs.into_iter().map(|dir| dir.clean()).collect()
+}
+
+async fn sub_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return dirs;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    dirs
+}
+
+/// `*` matches any number of characters
+fn matches_segment(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = name.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+
+    let mut parts = parts.collect::<Vec<_>>();
+    let Some(last) = parts.pop() else {
+        // no wildcard
+        return rest.is_empty();
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+async fn read_config(path: &Path) -> Option<ConfigFile> {
+    let content = tokio::fs::read_to_string(path).await.ok()?;
+    match serde_json::from_str(&strip_json_comments(&content)) {
+        Ok(config) => Some(config),
+        Err(err) => {
+            warn!(target: "graph", "Failed to parse {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Removes `//` and `/* */` comments (deno.jsonc), leaving string literals untouched
+pub(crate) fn strip_json_comments(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        output.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+#[test]
+fn test_strip_json_comments() {
+    let input = r#"{
+        // comment
+        "a": "http://example.com", /* block */
+        "b": "\"//\""
+    }"#;
+    let value: serde_json::Value = serde_json::from_str(&strip_json_comments(input)).unwrap();
+    assert_eq!(value["a"], "http://example.com");
+    assert_eq!(value["b"], "\"//\"");
+}
+
+#[test]
+fn test_matches_segment() {
+    assert!(matches_segment("*", "app"));
+    assert!(matches_segment("*-ui", "panel-ui"));
+    assert!(!matches_segment("*-ui", "panel-api"));
+    assert!(matches_segment("lib-*-*", "lib-a-b"));
+    assert!(!matches_segment("a*a", "a"));
+}
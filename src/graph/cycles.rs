@@ -0,0 +1,148 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::specifier::ModuleSpecifier;
+
+use super::{GraphModule, ModuleGraph};
+
+/// A set of local modules that (transitively) import each other.
+///
+/// The members are sorted, so two cycles containing the same modules compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ImportCycle {
+    pub modules: Vec<ModuleSpecifier>,
+}
+
+impl ModuleGraph {
+    /// Finds all import cycles among local `file:` modules.
+    pub fn find_cycles(&self) -> Vec<ImportCycle> {
+        let nodes = self
+            .modules()
+            .filter_map(|m| m.esm())
+            .filter(|m| m.specifier().scheme() == "file")
+            .collect::<Vec<_>>();
+
+        let index = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.specifier(), i))
+            .collect::<HashMap<Arc<ModuleSpecifier>, usize>>();
+
+        let edges = nodes
+            .iter()
+            .map(|m| {
+                m.lookup_table()
+                    .unwrap_or_default()
+                    .values()
+                    .filter_map(|dep| match dep {
+                        GraphModule::Esm(dep) => index.get(&dep.specifier()).copied(),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut cycles = strongly_connected_components(&edges)
+            .into_iter()
+            .filter(|component| component.len() > 1 || edges[component[0]].contains(&component[0]))
+            .map(|component| {
+                let mut modules = component
+                    .into_iter()
+                    .map(|i| nodes[i].specifier().as_ref().clone())
+                    .collect::<Vec<_>>();
+                modules.sort();
+                ImportCycle { modules }
+            })
+            .collect::<Vec<_>>();
+
+        cycles.sort_by(|a, b| a.modules.cmp(&b.modules));
+
+        cycles
+    }
+}
+
+/// Returns the cycles that are not part of the baseline.
+pub fn new_cycles<'a>(cycles: &'a [ImportCycle], baseline: &[ImportCycle]) -> Vec<&'a ImportCycle> {
+    let known = baseline.iter().collect::<HashSet<_>>();
+    cycles.iter().filter(|c| !known.contains(c)).collect()
+}
+
+/// Tarjan's algorithm, iterative so that deep import chains do not overflow the stack.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+    let mut next_index = 0;
+    let mut index = vec![usize::MAX; n];
+    let mut low_link = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+
+    for start in 0..n {
+        if index[start] != usize::MAX {
+            continue;
+        }
+
+        // (node, position in its edge list)
+        let mut call_stack = vec![(start, 0)];
+
+        while let Some((node, edge)) = call_stack.pop() {
+            if edge == 0 {
+                index[node] = next_index;
+                low_link[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+
+            if let Some(&next) = edges[node].get(edge) {
+                call_stack.push((node, edge + 1));
+                if index[next] == usize::MAX {
+                    call_stack.push((next, 0));
+                } else if on_stack[next] {
+                    low_link[node] = low_link[node].min(index[next]);
+                }
+                continue;
+            }
+
+            if low_link[node] == index[node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+
+            if let Some(&(parent, _)) = call_stack.last() {
+                low_link[parent] = low_link[parent].min(low_link[node]);
+            }
+        }
+    }
+
+    components
+}
+
+#[test]
+fn test_strongly_connected_components() {
+    // 0 -> 1 -> 2 -> 0, 3 -> 3, 4 -> 0
+    let edges = vec![vec![1], vec![2], vec![0], vec![3], vec![0]];
+
+    let mut components = strongly_connected_components(&edges)
+        .into_iter()
+        .map(|mut c| {
+            c.sort();
+            c
+        })
+        .collect::<Vec<_>>();
+    components.sort();
+
+    assert_eq!(components, vec![vec![0, 1, 2], vec![3], vec![4]]);
+}
@@ -0,0 +1,499 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::specifier::{MediaType, ModuleSpecifier};
+
+use super::{GraphModule, ModuleGraph};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleScheme {
+    File,
+    Https,
+    Jsr,
+    Npm,
+    Other,
+}
+
+impl ModuleScheme {
+    pub fn of(module: &GraphModule) -> Self {
+        match module {
+            GraphModule::Esm(m) => Self::of_specifier(&m.specifier()),
+            GraphModule::Npm(_) => ModuleScheme::Npm,
+            GraphModule::Virtual(_) => ModuleScheme::Other,
+        }
+    }
+
+    fn of_specifier(specifier: &ModuleSpecifier) -> Self {
+        match specifier.scheme() {
+            "file" => ModuleScheme::File,
+            "http" | "https" if specifier.host_str() == Some("jsr.io") => ModuleScheme::Jsr,
+            "http" | "https" => ModuleScheme::Https,
+            "jsr" => ModuleScheme::Jsr,
+            "npm" => ModuleScheme::Npm,
+            _ => ModuleScheme::Other,
+        }
+    }
+
+    pub fn parse(scheme: &str) -> Option<Self> {
+        match scheme {
+            "file" => Some(ModuleScheme::File),
+            "http" | "https" => Some(ModuleScheme::Https),
+            "jsr" => Some(ModuleScheme::Jsr),
+            "npm" => Some(ModuleScheme::Npm),
+            "other" => Some(ModuleScheme::Other),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Only include modules reachable from this entrypoint (all entrypoints if `None`)
+    pub entrypoint: Option<String>,
+    /// Only include modules at most this many imports away from the root
+    pub max_depth: Option<usize>,
+    /// Only include modules with one of these schemes (all if `None`)
+    pub schemes: Option<HashSet<ModuleScheme>>,
+    /// Collapse all modules of a npm or jsr package into a single node
+    pub collapse_packages: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeKind {
+    Esm,
+    Npm,
+    Virtual,
+    NpmPackage,
+    JsrPackage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportNode {
+    pub id: String,
+    pub label: String,
+    pub kind: NodeKind,
+    pub scheme: ModuleScheme,
+    pub media_type: Option<MediaType>,
+    /// Source bytes, summed up for collapsed jsr packages. Missing for npm modules and packages, `deno info`
+    /// doesn't report their size (the `size` command measures them in the npm cache)
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ExportEdge {
+    pub from: String,
+    pub to: String,
+    /// The specifier as written in the importing module
+    pub specifier: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportGraph {
+    pub nodes: Vec<ExportNode>,
+    pub edges: Vec<ExportEdge>,
+}
+
+impl ModuleGraph {
+    pub fn export(&self, options: &ExportOptions) -> ExportGraph {
+        let order = match &options.entrypoint {
+            Some(name) => self.walk_from(
+                self.entrypoint(name).map(GraphModule::Esm),
+                options.max_depth,
+            ),
+            None => self.walk(options.max_depth),
+        };
+
+        let included = |module: &GraphModule| {
+            options
+                .schemes
+                .as_ref()
+                .is_none_or(|schemes| schemes.contains(&ModuleScheme::of(module)))
+        };
+
+        let mut graph = ExportGraph::default();
+        let mut node_ids = HashSet::new();
+        let mut package_sizes: HashMap<String, u64> = HashMap::new();
+        let mut edges = HashSet::new();
+
+        for module in order.iter().filter(|m| included(m)) {
+            let node = self.export_node(module, options.collapse_packages);
+
+            if let (Some(size), NodeKind::NpmPackage | NodeKind::JsrPackage) =
+                (module.esm().map(|m| m.size()), node.kind)
+            {
+                *package_sizes.entry(node.id.clone()).or_default() += size;
+            }
+
+            if node_ids.insert(node.id.clone()) {
+                graph.nodes.push(node);
+            }
+        }
+
+        for module in order.iter().filter(|m| included(m)) {
+            let from = self.export_node(module, options.collapse_packages).id;
+
+            if let Some(esm) = module.esm() {
+                let mut table = esm
+                    .lookup_table()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                table.sort_by(|a, b| a.0.cmp(&b.0));
+
+                for (specifier, dep) in table {
+                    if !included(&dep) {
+                        continue;
+                    }
+                    let to = self.export_node(&dep, options.collapse_packages).id;
+                    if from != to && node_ids.contains(&to) {
+                        let edge = ExportEdge {
+                            from: from.clone(),
+                            to,
+                            specifier,
+                        };
+                        if edges.insert(edge.clone()) {
+                            graph.edges.push(edge);
+                        }
+                    }
+                }
+            }
+
+            if let (true, Some(npm)) = (options.collapse_packages, module.npm()) {
+                for dep in npm.package().dependencies() {
+                    let to = format!("npm:{}", dep);
+                    if node_ids.contains(&to) {
+                        let edge = ExportEdge {
+                            from: from.clone(),
+                            to,
+                            specifier: dep.name,
+                        };
+                        if edges.insert(edge.clone()) {
+                            graph.edges.push(edge);
+                        }
+                    }
+                }
+            }
+        }
+
+        for node in graph.nodes.iter_mut() {
+            if let Some(size) = package_sizes.get(&node.id) {
+                node.size = Some(*size);
+            }
+        }
+
+        graph
+    }
+
+    fn export_node(&self, module: &GraphModule, collapse_packages: bool) -> ExportNode {
+        let scheme = ModuleScheme::of(module);
+
+        if collapse_packages {
+            match module {
+                GraphModule::Npm(m) => {
+                    let id = format!("npm:{}", m.package().id());
+                    return ExportNode {
+                        label: id.clone(),
+                        id,
+                        kind: NodeKind::NpmPackage,
+                        scheme,
+                        media_type: None,
+                        size: None,
+                    };
+                }
+                GraphModule::Esm(m) if scheme == ModuleScheme::Jsr => {
+                    if let Some(package) = self.jsr_package_of(&m.specifier()) {
+                        let id = format!("jsr:{}", package.id());
+                        return ExportNode {
+                            label: id.clone(),
+                            id,
+                            kind: NodeKind::JsrPackage,
+                            scheme,
+                            media_type: None,
+                            size: Some(0),
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let specifier = module.specifier();
+
+        let label = specifier
+            .to_file_path()
+            .ok()
+            .and_then(|path| {
+                path.strip_prefix(&self.root_dir)
+                    .ok()
+                    .map(|p| p.to_string_lossy().replace("\\", "/"))
+            })
+            .unwrap_or_else(|| specifier.to_string());
+
+        match module {
+            GraphModule::Esm(m) => ExportNode {
+                id: specifier.to_string(),
+                label,
+                kind: NodeKind::Esm,
+                scheme,
+                media_type: Some(m.media_type()),
+                size: Some(m.size()),
+            },
+            GraphModule::Npm(_) => ExportNode {
+                id: specifier.to_string(),
+                label,
+                kind: NodeKind::Npm,
+                scheme,
+                media_type: None,
+                size: None,
+            },
+            GraphModule::Virtual(_) => ExportNode {
+                id: specifier.to_string(),
+                label,
+                kind: NodeKind::Virtual,
+                scheme,
+                media_type: None,
+                size: None,
+            },
+        }
+    }
+}
+
+impl ExportGraph {
+    /// Graphviz DOT
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph modules {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::NpmPackage | NodeKind::JsrPackage => ", shape=box3d",
+                NodeKind::Npm => ", shape=component",
+                NodeKind::Virtual => ", style=dashed",
+                NodeKind::Esm => "",
+            };
+            writeln!(
+                out,
+                "    \"{}\" [label=\"{}\"{}];",
+                dot_escape(&node.id),
+                dot_escape(&node.label),
+                shape
+            )
+            .unwrap();
+        }
+
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                dot_escape(&edge.from),
+                dot_escape(&edge.to),
+                dot_escape(&edge.specifier)
+            )
+            .unwrap();
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Mermaid flowchart
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+
+        let ids = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), format!("n{}", i)))
+            .collect::<HashMap<_, _>>();
+
+        for node in &self.nodes {
+            let (open, close) = match node.kind {
+                NodeKind::NpmPackage | NodeKind::JsrPackage => ("[[", "]]"),
+                NodeKind::Virtual => ("([", "])"),
+                NodeKind::Esm | NodeKind::Npm => ("[", "]"),
+            };
+            writeln!(
+                out,
+                "    {}{}\"{}\"{}",
+                ids[node.id.as_str()],
+                open,
+                mermaid_escape(&node.label),
+                close
+            )
+            .unwrap();
+        }
+
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "    {} -->|\"{}\"| {}",
+                ids[edge.from.as_str()],
+                mermaid_escape(&edge.specifier),
+                ids[edge.to.as_str()]
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+async fn fixture_graph() -> ModuleGraph {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use super::source::InfoFileSource;
+
+    let root =
+        ModuleSpecifier::parse("file:///D:/dev/technik-app/frontend/dev.client.tsx").unwrap();
+    let source = InfoFileSource {
+        path: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("research/info.json"),
+    };
+
+    let mut graph = ModuleGraph::new();
+    graph
+        .build_from(
+            &source,
+            &BTreeMap::from([("client".to_string(), root)]),
+            PathBuf::from("D:/dev/technik-app"),
+        )
+        .await
+        .unwrap();
+    graph
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(dot_escape(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+    assert_eq!(mermaid_escape(r#"a "b""#), "a #quot;b#quot;");
+
+    let graph = ExportGraph {
+        nodes: vec![
+            ExportNode {
+                id: "file:///a.ts".to_string(),
+                label: r#"a "quoted".ts"#.to_string(),
+                kind: NodeKind::Esm,
+                scheme: ModuleScheme::File,
+                media_type: None,
+                size: None,
+            },
+            ExportNode {
+                id: "npm:preact@10.0.0".to_string(),
+                label: "npm:preact@10.0.0".to_string(),
+                kind: NodeKind::NpmPackage,
+                scheme: ModuleScheme::Npm,
+                media_type: None,
+                size: None,
+            },
+        ],
+        edges: vec![ExportEdge {
+            from: "file:///a.ts".to_string(),
+            to: "npm:preact@10.0.0".to_string(),
+            specifier: "preact".to_string(),
+        }],
+    };
+
+    let dot = graph.to_dot();
+    assert!(dot.contains(r#"    "file:///a.ts" [label="a \"quoted\".ts"];"#));
+    assert!(dot.contains(r#"    "npm:preact@10.0.0" [label="npm:preact@10.0.0", shape=box3d];"#));
+    assert!(dot.contains(r#"    "file:///a.ts" -> "npm:preact@10.0.0" [label="preact"];"#));
+
+    let mermaid = graph.to_mermaid();
+    assert!(mermaid.contains(r#"    n0["a #quot;quoted#quot;.ts"]"#));
+    assert!(mermaid.contains(r#"    n1[["npm:preact@10.0.0"]]"#));
+    assert!(mermaid.contains(r#"    n0 -->|"preact"| n1"#));
+}
+
+#[tokio::test]
+async fn test_jsr_package_of() {
+    let graph = fixture_graph().await;
+    let specifier =
+        ModuleSpecifier::parse("https://jsr.io/@deno-plc/adapter-osc/1.1.1/src/ascii.ts").unwrap();
+    assert_eq!(
+        graph
+            .jsr_package_of(&specifier)
+            .map(|package| package.id().to_string()),
+        Some("@deno-plc/adapter-osc@1.1.1".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_collapse_packages() {
+    let graph = fixture_graph().await;
+
+    let export = graph.export(&ExportOptions {
+        collapse_packages: true,
+        ..Default::default()
+    });
+
+    assert!(export.nodes.iter().all(|node| node.kind != NodeKind::Npm));
+    assert!(
+        !export
+            .nodes
+            .iter()
+            .any(|node| node.kind == NodeKind::Esm && node.scheme == ModuleScheme::Jsr)
+    );
+    let osc = export
+        .nodes
+        .iter()
+        .find(|node| node.id == "jsr:@deno-plc/adapter-osc@1.1.1")
+        .unwrap();
+    assert_eq!(osc.kind, NodeKind::JsrPackage);
+    assert!(osc.size.is_some_and(|size| size > 0));
+    assert!(
+        export
+            .nodes
+            .iter()
+            .any(|node| node.kind == NodeKind::NpmPackage)
+    );
+
+    let ids = export
+        .nodes
+        .iter()
+        .map(|node| node.id.as_str())
+        .collect::<HashSet<_>>();
+    assert_eq!(ids.len(), export.nodes.len());
+    for edge in &export.edges {
+        assert_ne!(edge.from, edge.to);
+        assert!(ids.contains(edge.from.as_str()) && ids.contains(edge.to.as_str()));
+    }
+}
+
+#[tokio::test]
+async fn test_export_filters() {
+    let graph = fixture_graph().await;
+
+    let root_only = graph.export(&ExportOptions {
+        max_depth: Some(0),
+        ..Default::default()
+    });
+    assert_eq!(root_only.nodes.len(), 1);
+    assert!(root_only.edges.is_empty());
+
+    let local = graph.export(&ExportOptions {
+        schemes: Some(HashSet::from([ModuleScheme::File])),
+        ..Default::default()
+    });
+    assert!(!local.nodes.is_empty());
+    assert!(
+        local
+            .nodes
+            .iter()
+            .all(|node| node.scheme == ModuleScheme::File)
+    );
+    assert!(local.nodes.len() < graph.export(&ExportOptions::default()).nodes.len());
+}
@@ -1,28 +1,49 @@
+pub mod cycles;
 pub mod dependencies;
+pub mod export;
+pub mod snapshot;
+pub mod source;
 
 use std::{
-    collections::{HashMap, HashSet},
-    path::PathBuf,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 
-use crate::{npm::id::NPMPackageId, specifier::ModuleSpecifier};
+use crate::{
+    jsr::id::JsrPackageId,
+    metrics::metrics,
+    npm::id::NPMPackageId,
+    specifier::{MediaType, ModuleSpecifier},
+};
 use dependencies::DependencyLink;
 use tokio::fs::read_to_string;
+use tracing::{debug, instrument, warn};
 use url::Url;
 
-use crate::deno::info::{self, DenoInfo, EsmDependency, EsmModule, Module};
+use crate::deno::{
+    info::{self, DenoInfo, EsmDependency, EsmModule, Module},
+    lockfile::Lockfile,
+    workspace::Workspace,
+};
 
 #[derive(Debug, Default)]
 pub struct ModuleGraph {
     modules: HashMap<Arc<ModuleSpecifier>, GraphModule>,
     redirects: HashMap<Arc<ModuleSpecifier>, Arc<ModuleSpecifier>>,
     specifiers: HashSet<Arc<ModuleSpecifier>>,
-    root_specifier: Option<Arc<ModuleSpecifier>>,
-    root_module: Option<Arc<ESMGraphModule>>,
+    entrypoints: BTreeMap<String, Arc<ESMGraphModule>>,
     npm_packages: HashMap<String, Arc<NPMPackage>>,
+    jsr_packages: HashMap<String, Arc<JsrPackage>>,
     pub global_package_imports: HashMap<String, GraphModule>,
     pub root_dir: PathBuf,
+    pub workspace: Workspace,
+    /// Served remote modules are verified against this lockfile
+    pub lockfile: Option<Lockfile>,
+    /// Directory with the layout of the deno npm cache: the vendored packages, or the deno npm cache if npm
+    /// packages are verified against the lockfile
+    pub npm_dir: Option<PathBuf>,
 }
 
 const REDIRECT_LIMIT: usize = 10;
@@ -32,25 +53,78 @@ impl ModuleGraph {
         Default::default()
     }
 
-    pub fn root_specifier(&self) -> Option<Arc<ModuleSpecifier>> {
-        self.root_specifier.clone()
+    pub fn entrypoints(&self) -> &BTreeMap<String, Arc<ESMGraphModule>> {
+        &self.entrypoints
+    }
+
+    pub fn entrypoint(&self, name: &str) -> Option<Arc<ESMGraphModule>> {
+        self.entrypoints.get(name).cloned()
     }
 
-    pub fn root(&self) -> Option<Arc<ESMGraphModule>> {
-        self.root_module.clone()
+    /// Entrypoints and their direct imports, the browser requests them first on a page load
+    pub fn is_entry_or_direct_import(&self, specifier: &ModuleSpecifier) -> bool {
+        self.entrypoints.values().any(|entry| {
+            *entry.specifier == *specifier
+                || entry
+                    .dependencies
+                    .try_resolved()
+                    .is_some_and(|dependencies| {
+                        dependencies
+                            .values()
+                            .any(|module| *module.specifier() == *specifier)
+                    })
+        })
+    }
+
+    /// Transitive static imports of `specifier` in BFS order, at most `limit` modules.
+    /// npm packages are not followed, the browser requests them as a whole.
+    pub fn preload(&self, specifier: &ModuleSpecifier, limit: usize) -> Vec<GraphModule> {
+        let Some(GraphModule::Esm(root)) = self.get_module(specifier) else {
+            return Vec::new();
+        };
+
+        let mut visited = HashSet::from([root.specifier()]);
+        let mut queue = VecDeque::from([root]);
+        let mut preload = Vec::new();
+
+        while let Some(module) = queue.pop_front() {
+            for dependency in module.static_imports() {
+                if preload.len() >= limit {
+                    return preload;
+                }
+                if matches!(dependency, GraphModule::Virtual(_))
+                    || !visited.insert(dependency.specifier())
+                {
+                    continue;
+                }
+                if let GraphModule::Esm(module) = &dependency {
+                    queue.push_back(module.clone());
+                }
+                preload.push(dependency);
+            }
+        }
+
+        preload
     }
 
     pub fn as_arc(&mut self, spec: ModuleSpecifier) -> Arc<ModuleSpecifier> {
         let n = Arc::new(spec);
-        if self.specifiers.contains(&n) {
-            return self.specifiers.get(&n).unwrap().clone();
+        if let Some(existing) = self.specifiers.get(&n) {
+            existing.clone()
         } else {
             self.specifiers.insert(n.clone());
             n
         }
     }
 
-    pub async fn build(&mut self, info: DenoInfo, root_dir: PathBuf) {
+    #[instrument(target = "graph", level = "debug", skip_all, fields(modules = info.modules.len()))]
+    pub async fn build(
+        &mut self,
+        info: DenoInfo,
+        entrypoints: &BTreeMap<String, ModuleSpecifier>,
+        root_dir: PathBuf,
+    ) {
+        let start = Instant::now();
         self.root_dir = root_dir;
 
         for (long_name, package) in info.npm_packages {
@@ -65,7 +139,7 @@ impl ModuleGraph {
             self.npm_packages.insert(long_name, package);
         }
 
-        for (_name, package) in &self.npm_packages {
+        for package in self.npm_packages.values() {
             package.link(|name| self.npm_packages.get(name).cloned());
         }
 
@@ -82,7 +156,7 @@ impl ModuleGraph {
                             package.import_specifier(Arc::new(npm_module.specifier)),
                         ))
                     } else {
-                        eprintln!(
+                        warn!(target: "npm",
                             "Failed to resolve NPM module {} for {}, unknown reference",
                             npm_module.npm_package, npm_module.specifier
                         );
@@ -102,14 +176,7 @@ impl ModuleGraph {
             }
         }
 
-        self.root_specifier = Some(self.as_arc(info.roots[0].clone()));
-        self.root_module = self
-            .modules
-            .get(self.root_specifier.as_ref().unwrap())
-            .and_then(|m| match m {
-                GraphModule::Esm(m) => Some(m.clone()),
-                _ => None,
-            });
+        self.build_jsr_packages(&info.packages, &info.redirects);
 
         for (key, value) in info.redirects {
             let key = self.as_arc(key);
@@ -117,16 +184,28 @@ impl ModuleGraph {
             self.redirects.insert(key, value);
         }
 
-        for (_spec, module) in self.modules.iter() {
-            match module {
-                GraphModule::Esm(m) => {
-                    let global_packages =
-                        m.link(|specifier| self.get_module_with_redirect(specifier, 0));
-                    self.global_package_imports.extend(global_packages);
+        for module in self.modules.values() {
+            if let GraphModule::Esm(m) = module {
+                let global_packages =
+                    m.link(|specifier| self.get_module_with_redirect(specifier, 0));
+                self.global_package_imports.extend(global_packages);
+            }
+        }
+
+        for (name, specifier) in entrypoints {
+            match self.get_module_with_redirect(specifier, 0) {
+                Some(GraphModule::Esm(module)) => {
+                    self.entrypoints.insert(name.clone(), module);
+                }
+                _ => {
+                    warn!(target: "graph", "Entrypoint {} ({}) is not an ESM module", name, specifier);
                 }
-                _ => {}
             }
         }
+
+        let elapsed = start.elapsed();
+        metrics().graph_build.observe(elapsed);
+        debug!(target: "graph", build_ms = elapsed.as_secs_f64() * 1000.0, "Built module graph");
     }
 
     pub fn get_module(&self, specifier: &ModuleSpecifier) -> Option<GraphModule> {
@@ -146,7 +225,7 @@ impl ModuleGraph {
             if redirects < REDIRECT_LIMIT {
                 return self.get_module_with_redirect(redirect, redirects + 1);
             } else {
-                eprintln!("Redirect limit reached for {}", specifier);
+                warn!(target: "graph", "Redirect limit reached for {}", specifier);
             }
         }
 
@@ -156,6 +235,110 @@ impl ModuleGraph {
     pub fn get_npm_package(&self, id: &str) -> Option<Arc<NPMPackage>> {
         self.npm_packages.get(id).cloned()
     }
+
+    fn build_jsr_packages(
+        &mut self,
+        packages: &HashMap<String, String>,
+        redirects: &HashMap<ModuleSpecifier, ModuleSpecifier>,
+    ) {
+        let mut exports: HashMap<JsrPackageId, BTreeMap<String, Arc<ModuleSpecifier>>> =
+            HashMap::new();
+
+        for id in packages
+            .values()
+            .filter_map(|id| JsrPackageId::from_string(id))
+        {
+            exports.entry(id).or_default();
+        }
+
+        for specifier in self.modules.keys() {
+            if let Some((id, _)) = JsrPackageId::from_url(specifier) {
+                exports.entry(id).or_default();
+            }
+        }
+
+        for (from, to) in redirects {
+            if let (Some((_, subpath)), Some((id, _))) = (
+                JsrPackageId::parse_specifier(from.as_str()),
+                JsrPackageId::from_url(to),
+            ) {
+                let to = self.as_arc(to.clone());
+                exports.entry(id).or_default().insert(subpath, to);
+            }
+        }
+
+        for (id, exports) in exports {
+            self.jsr_packages
+                .insert(id.to_string(), Arc::new(JsrPackage { id, exports }));
+        }
+
+        // version requirements (ex. "@std/path@^1.0.0")
+        for (requirement, id) in packages {
+            if let Some(package) = self.jsr_packages.get(id).cloned() {
+                self.jsr_packages.insert(requirement.clone(), package);
+            }
+        }
+    }
+
+    pub fn get_jsr_package(&self, id: &str) -> Option<Arc<JsrPackage>> {
+        self.jsr_packages.get(id).cloned()
+    }
+
+    /// The jsr package a `https://jsr.io/...` module belongs to
+    pub fn jsr_package_of(&self, specifier: &ModuleSpecifier) -> Option<Arc<JsrPackage>> {
+        JsrPackageId::from_url(specifier).and_then(|(id, _)| self.get_jsr_package(&id.to_string()))
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &GraphModule> {
+        self.modules.values()
+    }
+
+    /// All modules reachable from the entrypoints in BFS order, optionally limited to `max_depth` imports.
+    /// Falls back to all modules if there are no entrypoints.
+    pub fn walk(&self, max_depth: Option<usize>) -> Vec<GraphModule> {
+        if self.entrypoints.is_empty() {
+            self.walk_from(self.modules().cloned(), max_depth)
+        } else {
+            self.walk_from(
+                self.entrypoints.values().cloned().map(GraphModule::Esm),
+                max_depth,
+            )
+        }
+    }
+
+    /// All modules reachable from `roots` in BFS order, optionally limited to `max_depth` imports.
+    pub fn walk_from(
+        &self,
+        roots: impl IntoIterator<Item = GraphModule>,
+        max_depth: Option<usize>,
+    ) -> Vec<GraphModule> {
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        let mut queue = roots.into_iter().map(|m| (m, 0)).collect::<VecDeque<_>>();
+
+        while let Some((module, depth)) = queue.pop_front() {
+            if !visited.insert(module.specifier()) {
+                continue;
+            }
+            order.push(module.clone());
+
+            if max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+
+            if let Some(esm) = module.esm() {
+                let mut deps = esm
+                    .lookup_table()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                deps.sort_by(|a, b| a.0.cmp(&b.0));
+                queue.extend(deps.into_iter().map(|(_, dep)| (dep, depth + 1)));
+            }
+        }
+
+        order
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -200,22 +383,69 @@ impl GraphModule {
 pub struct ESMGraphModule {
     specifier: Arc<ModuleSpecifier>,
     dependencies: DependencyLink<EsmDependency, GraphModule>,
+    /// Import specifiers that are only imported dynamically
+    dynamic_imports: HashSet<String>,
     local: PathBuf,
+    media_type: MediaType,
+    size: u64,
 }
 
 impl ESMGraphModule {
     fn from_esm(esm: EsmModule) -> Arc<Self> {
+        let dynamic_imports = esm
+            .dependencies
+            .iter()
+            .filter(|dep| dep.is_dynamic)
+            .map(|dep| dep.specifier.clone())
+            .collect();
+
         Arc::new(Self {
             specifier: Arc::new(esm.specifier),
             dependencies: DependencyLink::new(esm.dependencies),
+            dynamic_imports,
             local: esm.local,
+            media_type: *esm.media_type,
+            size: esm.size,
         })
     }
 
+    /// Module that is not part of the graph (ex. an inline script of an HTML entrypoint), its imports are
+    /// resolved by the caller
+    pub fn detached(
+        specifier: ModuleSpecifier,
+        local: PathBuf,
+        imports: HashMap<String, GraphModule>,
+    ) -> Arc<Self> {
+        let module = Self {
+            specifier: Arc::new(specifier),
+            dependencies: DependencyLink::new(Vec::new()),
+            dynamic_imports: HashSet::new(),
+            local,
+            media_type: MediaType::Tsx,
+            size: 0,
+        };
+        module.dependencies.set_resolved(imports).unwrap();
+        Arc::new(module)
+    }
+
     pub fn specifier(&self) -> Arc<ModuleSpecifier> {
         self.specifier.clone()
     }
 
+    /// Path of the source file (the deno cache entry for remote modules)
+    pub fn local(&self) -> &Path {
+        &self.local
+    }
+
+    pub fn media_type(&self) -> MediaType {
+        self.media_type
+    }
+
+    /// Size of the source file in bytes, as reported by `deno info`
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
     fn link(
         &self,
         resolve: impl Fn(&ModuleSpecifier) -> Option<GraphModule>,
@@ -235,13 +465,13 @@ impl ESMGraphModule {
 
                         resolved.insert(specifier.clone(), module);
                     } else {
-                        eprintln!(
+                        warn!(target: "graph",
                             "Failed to resolve dependency source {} for {} of {}",
                             dep_code_linking_section.specifier, specifier, self.specifier
                         );
                     }
                 } else {
-                    // eprintln!("Missing code section for dependency: {}", specifier);
+                    // einfo!(target: "graph", "Missing code section for dependency: {}", specifier);
                 }
             }
 
@@ -263,6 +493,24 @@ impl ESMGraphModule {
         self.dependencies.try_resolved().cloned()
     }
 
+    /// Resolved dependencies that are needed to evaluate the module (no dynamic imports)
+    pub fn static_imports(&self) -> Vec<GraphModule> {
+        self.dependencies
+            .try_resolved()
+            .map(|deps| {
+                let mut imports = deps
+                    .iter()
+                    .filter(|(import, _)| !self.dynamic_imports.contains(*import))
+                    .collect::<Vec<_>>();
+                imports.sort_by_key(|(import, _)| *import);
+                imports
+                    .into_iter()
+                    .map(|(_, module)| module.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     pub async fn load_code(&self) -> Result<String, std::io::Error> {
         read_to_string(self.local.clone()).await
     }
@@ -349,7 +597,7 @@ impl NPMPackage {
                 if let Some(module) = resolve(dep) {
                     resolved.insert(dep.clone(), module);
                 } else {
-                    eprintln!("Failed to resolve dependency {} from {}", dep, self.id);
+                    warn!(target: "npm", "Failed to resolve dependency {} from {}", dep, self.id);
                 }
             }
 
@@ -361,14 +609,50 @@ impl NPMPackage {
         &self.registry_url
     }
 
+    /// Directory of the extracted package inside the deno npm cache
+    pub fn local_dir(&self, npm_cache: &Path) -> PathBuf {
+        npm_cache
+            .join(self.registry_url.host_str().unwrap_or("registry.npmjs.org"))
+            .join(&self.id.name)
+            .join(&self.id.version)
+    }
+
+    /// Tarball integrity recorded in the `registry.json` of the npm cache. This is metadata only, the
+    /// extracted files are not hashed.
+    pub async fn registry_integrity(&self, npm_dir: &Path) -> Option<String> {
+        let path = self.local_dir(npm_dir).with_file_name("registry.json");
+        let content = tokio::fs::read_to_string(path).await.ok()?;
+        let metadata: serde_json::Value = serde_json::from_str(&content).ok()?;
+        metadata["versions"][&self.id.version]["dist"]["integrity"]
+            .as_str()
+            .map(|integrity| integrity.to_string())
+    }
+
     pub fn dependencies(&self) -> Vec<NPMPackageId> {
         self.dependencies
             .try_resolved()
-            .map(|deps| deps.iter().map(|(_, v)| v.id().clone()).collect())
+            .map(|deps| deps.values().map(|v| v.id().clone()).collect())
             .unwrap_or_default()
     }
 }
 
+#[derive(Debug)]
+pub struct JsrPackage {
+    id: JsrPackageId,
+    /// Export subpath (`.`, `./sub`) to module, as far as used by the graph
+    exports: BTreeMap<String, Arc<ModuleSpecifier>>,
+}
+
+impl JsrPackage {
+    pub fn id(&self) -> &JsrPackageId {
+        &self.id
+    }
+
+    pub fn exports(&self) -> &BTreeMap<String, Arc<ModuleSpecifier>> {
+        &self.exports
+    }
+}
+
 #[derive(Debug)]
 pub struct VirtualModule {
     specifier: Arc<ModuleSpecifier>,
@@ -385,3 +669,35 @@ impl VirtualModule {
         self.specifier.clone()
     }
 }
+
+#[tokio::test]
+async fn test_preload() {
+    use source::InfoFileSource;
+
+    let root =
+        ModuleSpecifier::parse("file:///D:/dev/technik-app/frontend/dev.client.tsx").unwrap();
+
+    let source = InfoFileSource {
+        path: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("research/info.json"),
+    };
+
+    let mut graph = ModuleGraph::new();
+    graph
+        .build_from(
+            &source,
+            &BTreeMap::from([("client".to_string(), root.clone())]),
+            PathBuf::from("D:/dev/technik-app"),
+        )
+        .await
+        .unwrap();
+
+    let preload = graph.preload(&root, 20);
+    assert!(!preload.is_empty() && preload.len() <= 20);
+    assert!(preload.iter().all(|module| *module.specifier() != root));
+
+    let unique = preload
+        .iter()
+        .map(|module| module.specifier())
+        .collect::<HashSet<_>>();
+    assert_eq!(unique.len(), preload.len());
+}
@@ -57,7 +57,47 @@ async function cache_server(version: string) {
     return cache_path;
 }
 
-export async function run_graph_server(config: FullConfig) {
+export interface GraphApi {
+    /**
+     * Base URL of the API, the port is the actual one if `graph_server_port` is 0
+     */
+    url: URL;
+    /**
+     * Trusts the self-signed certificate of the graph server (`graph_server_tls`)
+     */
+    client?: Deno.HttpClient;
+}
+
+export interface GraphServerReady extends GraphApi {
+    modules: number;
+}
+
+/**
+ * Requests `path` of the graph API with the given query parameters
+ */
+export function fetchGraphApi(
+    api: GraphApi,
+    path: string,
+    params: Record<string, string> = {},
+): Promise<Response> {
+    const url = new URL(path, api.url);
+    for (const [name, value] of Object.entries(params)) {
+        url.searchParams.set(name, value);
+    }
+    return fetch(url, { client: api.client });
+}
+
+export interface GraphServer {
+    process: Deno.ChildProcess;
+    /**
+     * Resolves once the graph is built and the server accepts requests
+     */
+    ready: Promise<GraphServerReady>;
+}
+
+export async function run_graph_server(
+    config: FullConfig,
+): Promise<GraphServer> {
     if (config.dev_use_cargo) {
         return run_graph_server_cargo(config);
     }
@@ -65,22 +105,158 @@ export async function run_graph_server(config: FullConfig) {
     const executable = await cache_server(version);
 
     const proc = new Deno.Command(executable, {
-        args: ["--json", toServerConfig(config)],
+        args: [
+            "--json",
+            toServerConfig(config),
+            "--log-json",
+            ...supervision_args(),
+        ],
+        stdin: "piped",
+        stdout: "piped",
+        stderr: "piped",
     });
 
-    return proc.spawn();
+    return wait_ready(proc.spawn());
 }
 
-export function run_graph_server_cargo(config: FullConfig) {
+export function run_graph_server_cargo(config: FullConfig): GraphServer {
     const proc = new Deno.Command("cargo", {
         args: [
             "run",
             "--",
             "--json",
             toServerConfig(config),
+            "--log-json",
+            // cargo is the parent process, only stdin is passed through
+            "--watch-stdin",
         ],
         cwd: join(fromFileUrl(import.meta.url), "../.."),
+        stdin: "piped",
+        stdout: "piped",
+        stderr: "piped",
+    });
+
+    return wait_ready(proc.spawn());
+}
+
+/**
+ * The graph server exits when this process exits or closes its stdin
+ */
+function supervision_args() {
+    return ["--parent-pid", `${Deno.pid}`, "--watch-stdin"];
+}
+
+/**
+ * Forwards the server logs to logtape and waits for the `ready` handshake (a JSON line on stdout)
+ */
+function wait_ready(process: Deno.ChildProcess): GraphServer {
+    forward_logs(process.stderr).catch((e) => {
+        logger.error`failed to read graph server logs: ${e}`;
+    });
+
+    const ready = new Promise<GraphServerReady>((resolve, reject) => {
+        read_lines(process.stdout, (line) => {
+            const event = parse_json(line);
+            if (event?.event === "ready" && !event.url) {
+                reject(
+                    new Error(
+                        `Graph server listens on the Unix socket ${event.socket}, the launcher only supports TCP`,
+                    ),
+                );
+            } else if (event?.event === "ready") {
+                logger.info`graph server ready at ${event.url}`;
+                resolve(
+                    http_client(event.ca as string | null).then((client) => ({
+                        url: new URL(event.url as string),
+                        client,
+                        modules: event.modules as number,
+                    })),
+                );
+            } else if (event?.event === "error") {
+                reject(new Error(`Graph server failed: ${event.error}`));
+            } else if (line.trim()) {
+                logger.debug`${line}`;
+            }
+        }).then(() => {
+            reject(new Error("Graph server exited before it was ready"));
+        }, reject);
+    });
+
+    return { process, ready };
+}
+
+/**
+ * Client that trusts the generated certificate `ca` (a PEM file) in addition to the system roots
+ */
+async function http_client(ca: string | null) {
+    if (!ca) {
+        return undefined;
+    }
+    return Deno.createHttpClient({
+        caCerts: [await Deno.readTextFile(ca)],
+    });
+}
+
+/**
+ * Log lines of the graph server (`--log-json`) are logged to `app.deno-plc.build.<target>`
+ */
+async function forward_logs(stderr: ReadableStream<Uint8Array>) {
+    await read_lines(stderr, (line) => {
+        const record = parse_json(line);
+        if (!record || typeof record.message !== "string") {
+            if (line.trim()) {
+                logger.warn`${line}`;
+            }
+            return;
+        }
+
+        const { message, level, target, timestamp: _, ...properties } =
+            record;
+        const child = logger.getChild(String(target ?? "server"));
+        // logtape interprets braces as placeholders
+        const template = message.replaceAll("{", "{{").replaceAll("}", "}}");
+
+        switch (level) {
+            case "TRACE":
+            case "DEBUG":
+                child.debug(template, properties);
+                break;
+            case "WARN":
+                child.warn(template, properties);
+                break;
+            case "ERROR":
+                child.error(template, properties);
+                break;
+            default:
+                child.info(template, properties);
+        }
     });
+}
+
+async function read_lines(
+    stream: ReadableStream<Uint8Array>,
+    on_line: (line: string) => void,
+) {
+    let buffer = "";
+    for await (const chunk of stream.pipeThrough(new TextDecoderStream())) {
+        buffer += chunk;
+        const lines = buffer.split("\n");
+        buffer = lines.pop() ?? "";
+        lines.forEach(on_line);
+    }
+    if (buffer) {
+        on_line(buffer);
+    }
+}
 
-    return proc.spawn();
+function parse_json(line: string): Record<string, unknown> | null {
+    if (!line.startsWith("{")) {
+        return null;
+    }
+    try {
+        const value = JSON.parse(line);
+        return typeof value === "object" && value !== null ? value : null;
+    } catch (_e) {
+        return null;
+    }
 }
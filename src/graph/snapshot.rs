@@ -0,0 +1,394 @@
+//! Snapshot of the graph for instant restarts.
+//!
+//! The snapshot contains the `deno info` graph the `ModuleGraph` was built from (modules, redirects,
+//! npm packages and the raw -> resolved dependency tables) together with the mtime, size and hash of
+//! every local module. On the next start only the local modules that changed are re-queried, the
+//! `ModuleGraph` (including `global_package_imports`) is then rebuilt in memory.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    deno::{
+        info::{DenoInfo, Module},
+        lockfile::sha256_hex,
+    },
+    specifier::ModuleSpecifier,
+};
+
+use super::source::{GraphSource, GraphSourceFuture};
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Changes to these files may change the resolution of every module, the snapshot is discarded
+const CONFIG_FILES: &[&str] = &[
+    "deno.json",
+    "deno.jsonc",
+    "deno.lock",
+    "package.json",
+    "import_map.json",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    /// Milliseconds since the unix epoch
+    pub mtime: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl FileStamp {
+    /// `None` if the file does not exist
+    pub async fn read(path: &Path) -> Result<Option<FileStamp>, String> {
+        let content = match tokio::fs::read(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        let (mtime, size) = file_metadata(path).await?;
+
+        Ok(Some(FileStamp {
+            mtime,
+            size,
+            sha256: sha256_hex(&content),
+        }))
+    }
+
+    /// Compares mtime and size first, the content is only hashed if they differ
+    pub async fn is_fresh(&self, path: &Path) -> Result<bool, String> {
+        Ok(self.check(path).await?.is_some())
+    }
+
+    /// Stamp of the unchanged file with its current mtime, `None` if the file changed
+    pub async fn check(&self, path: &Path) -> Result<Option<FileStamp>, String> {
+        let (mtime, size) = match file_metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+
+        if size != self.size {
+            return Ok(None);
+        }
+
+        if mtime == self.mtime {
+            return Ok(Some(self.clone()));
+        }
+
+        let content = tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        Ok((sha256_hex(&content) == self.sha256).then(|| FileStamp {
+            mtime,
+            ..self.clone()
+        }))
+    }
+}
+
+async fn file_metadata(path: &Path) -> Result<(u64, u64), String> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|mtime| mtime.as_millis() as u64)
+        .unwrap_or_default();
+
+    Ok((mtime, metadata.len()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphSnapshot {
+    version: u32,
+    /// Roots the graph was requested for
+    pub roots: Vec<ModuleSpecifier>,
+    /// Local module -> stamp at the time the snapshot was taken. Remote modules are immutable.
+    pub modules: BTreeMap<ModuleSpecifier, FileStamp>,
+    /// Config file (relative to the root dir) -> stamp, `None` if it did not exist
+    pub config_files: BTreeMap<String, Option<FileStamp>>,
+    pub info: DenoInfo,
+}
+
+/// Result of validating a snapshot against the files on disk
+#[derive(Debug, PartialEq, Eq)]
+pub enum Staleness {
+    /// `refreshed` if files were touched without changing their content, their stamps were updated
+    Fresh { refreshed: bool },
+    /// These local modules changed and have to be re-queried
+    Modules(Vec<ModuleSpecifier>),
+    /// Config files changed or modules were removed, the whole graph has to be rebuilt
+    Full,
+}
+
+impl GraphSnapshot {
+    pub async fn capture(
+        info: DenoInfo,
+        roots: &[ModuleSpecifier],
+        root_dir: &Path,
+    ) -> Result<GraphSnapshot, String> {
+        let mut modules = BTreeMap::new();
+
+        for module in &info.modules {
+            let Module::Esm(module) = module else {
+                continue;
+            };
+            if module.specifier.scheme() != "file" {
+                continue;
+            }
+            if let Some(stamp) = FileStamp::read(&module.local).await? {
+                modules.insert(module.specifier.clone(), stamp);
+            }
+        }
+
+        let mut config_files = BTreeMap::new();
+        for file in CONFIG_FILES {
+            config_files.insert(
+                file.to_string(),
+                FileStamp::read(&root_dir.join(file)).await?,
+            );
+        }
+
+        Ok(GraphSnapshot {
+            version: SNAPSHOT_VERSION,
+            roots: roots.to_vec(),
+            modules,
+            config_files,
+            info,
+        })
+    }
+
+    pub async fn load(path: &Path) -> Result<GraphSnapshot, String> {
+        let content = tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let snapshot: GraphSnapshot = serde_json::from_slice(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Unsupported graph snapshot version: {}",
+                snapshot.version
+            ));
+        }
+
+        Ok(snapshot)
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        tokio::fs::write(path, serde_json::to_vec(self).unwrap())
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Stamps of unchanged files with a new mtime are updated, so they are not hashed again once the
+    /// snapshot is saved
+    pub async fn staleness(&mut self, root_dir: &Path) -> Result<Staleness, String> {
+        let mut refreshed = false;
+
+        for (file, stamp) in self.config_files.iter_mut() {
+            let path = root_dir.join(file);
+            match stamp {
+                Some(stamp) => match stamp.check(&path).await? {
+                    Some(current) => {
+                        refreshed |= current != *stamp;
+                        *stamp = current;
+                    }
+                    None => return Ok(Staleness::Full),
+                },
+                None if path.exists() => return Ok(Staleness::Full),
+                None => {}
+            }
+        }
+
+        let mut changed = Vec::new();
+
+        for (specifier, stamp) in self.modules.iter_mut() {
+            let Ok(path) = specifier.to_file_path() else {
+                return Ok(Staleness::Full);
+            };
+            if !path.exists() {
+                return Ok(Staleness::Full);
+            }
+            match stamp.check(&path).await? {
+                Some(current) => {
+                    refreshed |= current != *stamp;
+                    *stamp = current;
+                }
+                None => changed.push(specifier.clone()),
+            }
+        }
+
+        if changed.is_empty() {
+            Ok(Staleness::Fresh { refreshed })
+        } else {
+            Ok(Staleness::Modules(changed))
+        }
+    }
+}
+
+/// Loads the graph from a snapshot and only re-queries changed local modules from `inner`.
+/// The snapshot is (re)written whenever `inner` was queried.
+pub struct SnapshotSource {
+    pub inner: Box<dyn GraphSource>,
+    pub path: PathBuf,
+    pub root_dir: PathBuf,
+}
+
+impl SnapshotSource {
+    async fn load_snapshot(&self, roots: &[ModuleSpecifier]) -> Result<DenoInfo, String> {
+        let snapshot = match GraphSnapshot::load(&self.path).await {
+            Ok(snapshot) if snapshot.roots == roots => Some(snapshot),
+            Ok(_) => {
+                info!(target: "graph", "Entrypoints changed, discarding graph snapshot");
+                None
+            }
+            Err(err) => {
+                if self.path.exists() {
+                    warn!(target: "graph", "{}", err);
+                }
+                None
+            }
+        };
+
+        let info = match snapshot {
+            Some(mut snapshot) => match snapshot.staleness(&self.root_dir).await? {
+                Staleness::Fresh { refreshed } => {
+                    info!(target: "graph", "Using graph snapshot {}", self.path.display());
+                    if refreshed && let Err(err) = snapshot.save(&self.path).await {
+                        warn!(target: "graph", "{}", err);
+                    }
+                    return Ok(snapshot.info);
+                }
+                Staleness::Modules(changed) => {
+                    info!(target: "graph",
+                        "{} modules changed since the graph snapshot, re-querying them",
+                        changed.len()
+                    );
+                    snapshot.info.update(self.inner.load(&changed).await?);
+                    // imports removed from the changed modules leave unreachable modules behind
+                    snapshot.info.prune();
+                    snapshot.info
+                }
+                Staleness::Full => {
+                    info!(target: "graph", "Graph snapshot is stale");
+                    self.inner.load(roots).await?
+                }
+            },
+            None => self.inner.load(roots).await?,
+        };
+
+        match GraphSnapshot::capture(info.clone(), roots, &self.root_dir).await {
+            Ok(snapshot) => {
+                if let Err(err) = snapshot.save(&self.path).await {
+                    warn!(target: "graph", "{}", err);
+                }
+            }
+            Err(err) => warn!(target: "graph", "Failed to snapshot graph: {}", err),
+        }
+
+        Ok(info)
+    }
+}
+
+impl GraphSource for SnapshotSource {
+    fn load<'a>(&'a self, roots: &'a [ModuleSpecifier]) -> GraphSourceFuture<'a> {
+        Box::pin(self.load_snapshot(roots))
+    }
+}
+
+#[tokio::test]
+async fn test_file_stamp() {
+    let path = std::env::temp_dir().join(format!("deno-plc-stamp-{}.ts", std::process::id()));
+
+    tokio::fs::write(&path, "export const a = 1;")
+        .await
+        .unwrap();
+    let stamp = FileStamp::read(&path).await.unwrap().unwrap();
+    assert!(stamp.is_fresh(&path).await.unwrap());
+
+    tokio::fs::write(&path, "export const a = 12;")
+        .await
+        .unwrap();
+    assert!(!stamp.is_fresh(&path).await.unwrap());
+
+    tokio::fs::remove_file(&path).await.unwrap();
+    assert!(!stamp.is_fresh(&path).await.unwrap());
+    assert_eq!(FileStamp::read(&path).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_staleness_refreshes_touched_files() {
+    let dir = std::env::temp_dir().join(format!("deno-plc-snapshot-{}", std::process::id()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let path = dir.join("main.ts");
+    tokio::fs::write(&path, "export const a = 1;")
+        .await
+        .unwrap();
+
+    let specifier = ModuleSpecifier::from_file_path(&path).unwrap();
+    let info: DenoInfo = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "roots": [specifier],
+        "modules": [
+            { "kind": "esm", "specifier": specifier, "local": path, "mediaType": "TypeScript" }
+        ],
+        "npmPackages": {},
+        "packages": {},
+        "redirects": {}
+    }))
+    .unwrap();
+
+    let mut snapshot = GraphSnapshot::capture(info, std::slice::from_ref(&specifier), &dir)
+        .await
+        .unwrap();
+    assert_eq!(
+        snapshot.staleness(&dir).await.unwrap(),
+        Staleness::Fresh { refreshed: false }
+    );
+
+    // touched, the content is unchanged
+    let mtime = snapshot.modules[&specifier].mtime;
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+
+    assert_eq!(
+        snapshot.staleness(&dir).await.unwrap(),
+        Staleness::Fresh { refreshed: true }
+    );
+    assert_ne!(snapshot.modules[&specifier].mtime, mtime);
+    assert_eq!(
+        snapshot.staleness(&dir).await.unwrap(),
+        Staleness::Fresh { refreshed: false }
+    );
+
+    tokio::fs::write(&path, "export const a = 2;")
+        .await
+        .unwrap();
+    assert_eq!(
+        snapshot.staleness(&dir).await.unwrap(),
+        Staleness::Modules(vec![specifier])
+    );
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+}
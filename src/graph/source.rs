@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, future::Future, path::PathBuf, pin::Pin};
+
+use tracing::warn;
+
+use crate::{
+    deno::info::{DenoInfo, call_deno_info, parse_deno_info},
+    specifier::ModuleSpecifier,
+    vendor,
+};
+
+use super::ModuleGraph;
+
+pub type GraphSourceFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<DenoInfo, String>> + Send + 'a>>;
+
+/// Provides the raw module graph (in the `deno info --json` schema) for a set of roots.
+/// `Send + Sync`, so the futures of an embedded `DevServer` can be spawned.
+pub trait GraphSource: Send + Sync {
+    fn load<'a>(&'a self, roots: &'a [ModuleSpecifier]) -> GraphSourceFuture<'a>;
+}
+
+/// Spawns `deno info --json` for every root
+#[derive(Debug, Clone)]
+pub struct DenoInfoSource {
+    pub deno_executable: String,
+    /// Working directory of the deno process
+    pub dir: PathBuf,
+}
+
+impl GraphSource for DenoInfoSource {
+    fn load<'a>(&'a self, roots: &'a [ModuleSpecifier]) -> GraphSourceFuture<'a> {
+        Box::pin(call_deno_info(&self.deno_executable, &self.dir, roots))
+    }
+}
+
+/// Reads a saved `deno info --json` output (ex. `research/info.json`)
+#[derive(Debug, Clone)]
+pub struct InfoFileSource {
+    pub path: PathBuf,
+}
+
+impl GraphSource for InfoFileSource {
+    fn load<'a>(&'a self, roots: &'a [ModuleSpecifier]) -> GraphSourceFuture<'a> {
+        Box::pin(async move {
+            let json = tokio::fs::read(&self.path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+
+            let info = parse_deno_info(&json)?;
+
+            for root in roots {
+                if !info.roots.contains(root) {
+                    warn!(target: "graph", "{} is not a root of {}", root, self.path.display());
+                }
+            }
+
+            Ok(info)
+        })
+    }
+}
+
+/// Graph saved by the `vendor` command
+#[derive(Debug, Clone)]
+pub struct VendorSource {
+    pub vendor_dir: PathBuf,
+    pub root_dir: PathBuf,
+}
+
+impl GraphSource for VendorSource {
+    fn load<'a>(&'a self, _roots: &'a [ModuleSpecifier]) -> GraphSourceFuture<'a> {
+        Box::pin(vendor::load_vendored(&self.vendor_dir, &self.root_dir))
+    }
+}
+
+/// Builds the graph in-process with `deno_graph` from the deno cache
+#[cfg(feature = "native")]
+#[derive(Debug, Clone)]
+pub struct NativeSource {
+    pub deno_dir: PathBuf,
+    /// Directory of the `deno.json` providing the import map and the JSX import source
+    pub config_dir: PathBuf,
+    /// npm packages and jsr versions are resolved from the lockfile
+    pub lockfile: Option<PathBuf>,
+}
+
+#[cfg(feature = "native")]
+impl GraphSource for NativeSource {
+    fn load<'a>(&'a self, roots: &'a [ModuleSpecifier]) -> GraphSourceFuture<'a> {
+        Box::pin(async move {
+            let lockfile = match &self.lockfile {
+                Some(path) => Some(crate::deno::lockfile::Lockfile::load(path).await?),
+                None => None,
+            };
+
+            // the deno_graph loader futures are not Send, the graph is built on a blocking thread
+            let deno_dir = self.deno_dir.clone();
+            let config_dir = self.config_dir.clone();
+            let roots = roots.to_vec();
+            let runtime = tokio::runtime::Handle::current();
+            tokio::task::spawn_blocking(move || {
+                runtime.block_on(crate::deno::native::build_native_info(
+                    &deno_dir,
+                    &config_dir,
+                    &roots,
+                    lockfile.as_ref(),
+                ))
+            })
+            .await
+            .map_err(|e| format!("Failed to build the graph: {}", e))?
+        })
+    }
+}
+
+impl ModuleGraph {
+    /// Loads the graph for the entrypoints from `source` and builds it
+    pub async fn build_from(
+        &mut self,
+        source: &dyn GraphSource,
+        entrypoints: &BTreeMap<String, ModuleSpecifier>,
+        root_dir: PathBuf,
+    ) -> Result<(), String> {
+        let roots = entrypoints.values().cloned().collect::<Vec<_>>();
+        let info = source.load(&roots).await?;
+        self.build(info, entrypoints, root_dir).await;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_info_file_source() {
+    let root =
+        ModuleSpecifier::parse("file:///D:/dev/technik-app/frontend/dev.client.tsx").unwrap();
+
+    let source = InfoFileSource {
+        path: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("research/info.json"),
+    };
+
+    let mut graph = ModuleGraph::new();
+    graph
+        .build_from(
+            &source,
+            &BTreeMap::from([("client".to_string(), root.clone())]),
+            PathBuf::from("D:/dev/technik-app"),
+        )
+        .await
+        .unwrap();
+
+    let client = graph.entrypoint("client").unwrap();
+    assert_eq!(*client.specifier(), root);
+    assert!(client.lookup_table().is_some_and(|table| !table.is_empty()));
+}
@@ -0,0 +1,252 @@
+/**
+ * @license GPL-3.0-or-later
+ * Deno-PLC build
+ *
+ * Copyright (C) 2025 Hans Schallmoser
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+import type { Hono } from "hono";
+import { relative, SEPARATOR } from "@std/path";
+import type { FullConfig } from "../config.ts";
+
+/**
+ * Injected into HTML pages and imported by modules transformed with `hmr` (`HMR_CLIENT` in src/html/mod.rs)
+ */
+export const HMR_CLIENT_PATH = "/@hmr/client.js";
+
+const HMR_EVENTS_PATH = "/@hmr/events";
+
+/**
+ * Keeps a record per module URL, components are swapped with prefresh.
+ * Modules that neither accept updates nor only export components reload the page.
+ */
+const client = `const records = new Map();
+
+class HotContext {
+    data = {};
+    accepted = [];
+    disposers = [];
+    components = 0;
+    saved = new Map();
+
+    accept(cb = () => {}) {
+        this.accepted.push(cb);
+    }
+
+    dispose(cb) {
+        this.disposers.push(cb);
+    }
+
+    invalidate() {
+        location.reload();
+    }
+
+    register(type, id) {
+        const prefresh = self.__PREFRESH__;
+        if (!prefresh || typeof type !== "function") {
+            return;
+        }
+        this.components++;
+        const old = this.saved.get(id);
+        this.saved.set(id, type);
+        if (old && old !== type) {
+            prefresh.replaceComponent(old, type, true);
+        }
+        prefresh.register(type, id);
+    }
+
+    signature() {
+        let status = "begin";
+        let saved;
+        return (type, key, forceReset, getCustomHooks) => {
+            saved ??= type;
+            if (self.__PREFRESH__) {
+                status = self.__PREFRESH__.sign(
+                    type || saved,
+                    key,
+                    forceReset,
+                    getCustomHooks,
+                    status,
+                );
+            }
+            return type;
+        };
+    }
+}
+
+export function createHotContext(url) {
+    const path = new URL(url).pathname;
+    let context = records.get(path);
+    if (!context) {
+        context = new HotContext();
+        records.set(path, context);
+    }
+    // a new version registers again
+    context.accepted = [];
+    context.disposers = [];
+    context.components = 0;
+    return context;
+}
+
+async function update(path) {
+    const context = records.get(path);
+    if (!context) {
+        // not loaded on this page
+        return;
+    }
+    const refreshable = !!self.__PREFRESH__ && context.components > 0;
+    if (context.accepted.length === 0 && !refreshable) {
+        location.reload();
+        return;
+    }
+    const accepted = context.accepted;
+    for (const dispose of context.disposers) {
+        dispose(context.data);
+    }
+    try {
+        const module = await import(path + "?t=" + Date.now());
+        if (
+            accepted.length === 0 &&
+            !Object.values(module).every((value) => typeof value === "function")
+        ) {
+            location.reload();
+            return;
+        }
+        for (const cb of accepted) {
+            cb(module);
+        }
+        self.__PREFRESH__?.flush?.();
+    } catch (e) {
+        console.error(e);
+        location.reload();
+    }
+}
+
+const events = new EventSource(${JSON.stringify(HMR_EVENTS_PATH)});
+events.addEventListener("update", async (event) => {
+    for (const path of JSON.parse(event.data)) {
+        await update(path);
+    }
+});
+events.addEventListener("reload", () => location.reload());
+`;
+
+/**
+ * Hot module replacement: changed modules of the project are replaced, other changes reload the page
+ */
+export function hmr_routes(app: Hono, config: FullConfig) {
+    const clients = new Set<ReadableStreamDefaultController<Uint8Array>>();
+    const encoder = new TextEncoder();
+    let watching = false;
+    let timeout: number | null = null;
+    let changed = new Set<string>();
+
+    function send(event: string, data: string) {
+        const message = encoder.encode(`event: ${event}\ndata: ${data}\n\n`);
+        for (const client of clients) {
+            try {
+                client.enqueue(message);
+            } catch (_e) {
+                clients.delete(client);
+            }
+        }
+    }
+
+    function flush() {
+        timeout = null;
+        const paths = [...changed];
+        changed = new Set();
+
+        const modules = paths.map((path) =>
+            relative(config.root_dir, path).split(SEPARATOR)
+        );
+        if (
+            modules.every((segments) =>
+                segments[0] !== ".." && MODULE_EXTENSION.test(segments.at(-1)!)
+            )
+        ) {
+            send(
+                "update",
+                JSON.stringify(
+                    modules.map((segments) => "/" + segments.join("/")),
+                ),
+            );
+        } else {
+            send("reload", "");
+        }
+    }
+
+    async function watch() {
+        watching = true;
+        for await (const event of Deno.watchFs(config.root_dir)) {
+            if (event.kind === "access") {
+                continue;
+            }
+            for (const path of event.paths) {
+                if (!ignored(path)) {
+                    changed.add(path);
+                }
+            }
+            if (changed.size === 0) {
+                continue;
+            }
+            // editors write several events per save
+            if (timeout !== null) {
+                clearTimeout(timeout);
+            }
+            timeout = setTimeout(flush, 100);
+        }
+    }
+
+    app.get(HMR_CLIENT_PATH, (c) => {
+        return c.body(client, 200, {
+            "Content-Type": "application/javascript;charset=UTF-8",
+            "Cache-Control": "no-store",
+        });
+    });
+
+    app.get(HMR_EVENTS_PATH, () => {
+        if (!watching) {
+            watch().catch((e) => {
+                watching = false;
+                config.logger.error`HMR file watcher failed: ${e}`;
+            });
+        }
+
+        let controller: ReadableStreamDefaultController<Uint8Array>;
+        const body = new ReadableStream<Uint8Array>({
+            start(c) {
+                controller = c;
+                clients.add(controller);
+            },
+            cancel() {
+                clients.delete(controller);
+            },
+        });
+
+        return new Response(body, {
+            headers: {
+                "Content-Type": "text/event-stream",
+                "Cache-Control": "no-store",
+            },
+        });
+    });
+}
+
+const MODULE_EXTENSION = /\.(ts|tsx|js|jsx|mts|mjs)$/;
+
+function ignored(path: string): boolean {
+    return /[\\/](\.git|node_modules|dist)[\\/]/.test(path);
+}
@@ -0,0 +1,585 @@
+//! HTML entrypoints (ex. `index.html`).
+//!
+//! `<script type="module" src>` and the imports of inline module scripts are roots of the graph. When a page is
+//! rendered, the script sources are rewritten like the imports of transformed modules (dev server URLs or the
+//! files of the `html` command), inline scripts are transformed, the transitive imports are preloaded and in dev
+//! the HMR client is injected.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use lol_html::{RewriteStrSettings, element, html_content::ContentType, rewrite_str, text};
+use path_clean::PathClean;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use crate::{
+    config::{SourceMapMode, SourceMapOptions, Target},
+    graph::{ESMGraphModule, GraphModule, ModuleGraph},
+    specifier::ModuleSpecifier,
+    transpiler::{
+        Priority, TransformPool,
+        transform::{ModuleUrls, TransformOptions, collect_imports, static_path},
+    },
+};
+
+/// Served by the dev server (src/hmr/mod.ts), replaces changed modules and reloads the page otherwise
+pub const HMR_CLIENT: &str = "/@hmr/client.js";
+
+/// Modules preloaded per page
+const PRELOAD_LIMIT: usize = 100;
+
+const MODULE_SCRIPT: &str = "script[type=module]";
+
+#[derive(Debug, Clone)]
+pub enum HtmlScript {
+    /// `src` as written in the page and the module it resolves to
+    External {
+        src: String,
+        specifier: Option<ModuleSpecifier>,
+    },
+    Inline {
+        code: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct HtmlPage {
+    pub path: PathBuf,
+    pub specifier: ModuleSpecifier,
+    pub html: String,
+    /// Module scripts in document order
+    pub scripts: Vec<HtmlScript>,
+}
+
+impl HtmlPage {
+    /// Reads the page and discovers its module scripts, absolute paths (`/app.tsx`) are relative to `root_dir`
+    pub async fn load(path: &Path, root_dir: &Path) -> Result<HtmlPage, String> {
+        let html = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let specifier = ModuleSpecifier::from_file_path(path)
+            .map_err(|_| format!("Invalid HTML path {}", path.display()))?;
+
+        let scripts = discover_scripts(&html)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+            .into_iter()
+            .map(|(src, code)| match src {
+                Some(src) => HtmlScript::External {
+                    specifier: resolve(&src, &specifier, root_dir),
+                    src,
+                },
+                None => HtmlScript::Inline { code },
+            })
+            .collect();
+
+        Ok(HtmlPage {
+            path: path.to_path_buf(),
+            specifier,
+            html,
+            scripts,
+        })
+    }
+
+    /// Modules the page needs: the external scripts and the imports of the inline scripts.
+    /// Bare imports of inline scripts are resolved with the imports of the local modules.
+    pub fn roots(&self, root_dir: &Path) -> Vec<ModuleSpecifier> {
+        let mut roots = Vec::new();
+
+        for script in &self.scripts {
+            match script {
+                HtmlScript::External { specifier, .. } => roots.extend(specifier.clone()),
+                HtmlScript::Inline { code } => match collect_imports(code) {
+                    Ok(imports) => roots.extend(
+                        imports
+                            .iter()
+                            .filter_map(|import| resolve(import, &self.specifier, root_dir)),
+                    ),
+                    Err(err) => warn!(target: "graph",
+                        "Failed to parse inline script of {}: {}",
+                        self.path.display(),
+                        err
+                    ),
+                },
+            }
+        }
+
+        let mut seen = HashSet::new();
+        roots.retain(|root| seen.insert(root.clone()));
+        roots
+    }
+
+    /// Graph modules of the scripts and the imports of the inline scripts
+    pub fn modules(&self, graph: &ModuleGraph) -> Vec<GraphModule> {
+        let mut modules = Vec::new();
+
+        for (index, script) in self.scripts.iter().enumerate() {
+            match script {
+                HtmlScript::External { src, specifier } => {
+                    modules.extend(script_module(graph, src, specifier.as_ref()))
+                }
+                HtmlScript::Inline { code } => {
+                    let module = self.inline_module(index, code, graph);
+                    modules.extend(module.lookup_table().unwrap_or_default().into_values());
+                }
+            }
+        }
+
+        modules
+    }
+
+    /// Rewrites the page for the browser, inline scripts are lowered to `target`. `dev` injects the HMR client.
+    pub async fn render(
+        &self,
+        graph: Arc<ModuleGraph>,
+        transpiler: &TransformPool,
+        target: &Target,
+        dev: bool,
+        urls: ModuleUrls,
+    ) -> Result<String, String> {
+        let mut replacements = Vec::new();
+        let mut preload_roots = Vec::new();
+
+        // inline scripts are transformed first, the rewriter is synchronous
+        for (index, script) in self.scripts.iter().enumerate() {
+            match script {
+                HtmlScript::External { src, specifier } => {
+                    match script_module(&graph, src, specifier.as_ref()) {
+                        Some(module) => {
+                            replacements.push(urls.url(&graph, &module));
+                            preload_roots.push(module.specifier());
+                        }
+                        None => {
+                            warn!(target: "graph",
+                                "Script {} of {} is not part of the graph",
+                                src,
+                                self.path.display()
+                            );
+                            replacements.push(src.clone());
+                        }
+                    }
+                }
+                HtmlScript::Inline { code } => {
+                    let module = self.inline_module(index, code, &graph);
+                    for import in module.static_imports() {
+                        preload_roots.push(import.specifier());
+                    }
+
+                    let result = transpiler
+                        .schedule(
+                            TransformOptions {
+                                code: code.clone(),
+                                hmr: false,
+                                graph: graph.clone(),
+                                module,
+                                // there is no URL to serve an external map at
+                                source_map: SourceMapOptions {
+                                    mode: SourceMapMode::None,
+                                    ..Default::default()
+                                },
+                                target: target.clone(),
+                                urls,
+                            },
+                            Priority::High,
+                        )
+                        .await
+                        .result()
+                        .await
+                        .ok_or_else(|| {
+                            format!(
+                                "Failed to transform inline script {} of {}",
+                                index,
+                                self.path.display()
+                            )
+                        })?;
+
+                    replacements.push(result.code);
+                }
+            }
+        }
+
+        let mut head = String::new();
+        if dev {
+            head.push_str(&format!(
+                "<script type=\"module\" src=\"{}\"></script>",
+                HMR_CLIENT
+            ));
+        }
+        for url in preload_urls(&graph, &preload_roots, urls) {
+            head.push_str(&format!(
+                "<link rel=\"modulepreload\" href=\"{}\">",
+                escape_attribute(&url)
+            ));
+        }
+
+        let script_index = Cell::new(0);
+        let inline_script = Cell::new(None);
+        let head_found = Cell::new(false);
+
+        let html = rewrite_str(
+            &self.html,
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("head", |el| {
+                        if !head_found.replace(true) {
+                            el.prepend(&head, ContentType::Html);
+                        }
+                        Ok(())
+                    }),
+                    element!(MODULE_SCRIPT, |el| {
+                        let index = script_index.get();
+                        script_index.set(index + 1);
+
+                        match self.scripts.get(index) {
+                            Some(HtmlScript::External { .. }) => {
+                                el.set_attribute("src", &replacements[index])?
+                            }
+                            Some(HtmlScript::Inline { .. }) => inline_script.set(Some(index)),
+                            None => {}
+                        }
+                        Ok(())
+                    }),
+                    text!(MODULE_SCRIPT, |chunk| {
+                        if let Some(index) = inline_script.get() {
+                            if chunk.last_in_text_node() {
+                                chunk.replace(&replacements[index], ContentType::Html);
+                                inline_script.set(None);
+                            } else {
+                                chunk.remove();
+                            }
+                        }
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::new()
+            },
+        )
+        .map_err(|e| format!("Failed to rewrite {}: {}", self.path.display(), e))?;
+
+        if head_found.get() {
+            Ok(html)
+        } else {
+            Ok(head + html.as_str())
+        }
+    }
+
+    /// Inline scripts are transformed as `<page>#inline-<index>`, their imports resolved like the browser would
+    fn inline_module(&self, index: usize, code: &str, graph: &ModuleGraph) -> Arc<ESMGraphModule> {
+        let mut specifier = self.specifier.clone();
+        specifier.set_fragment(Some(&format!("inline-{}", index)));
+
+        let imports = collect_imports(code)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|import| {
+                let module = match resolve(&import, &self.specifier, &graph.root_dir) {
+                    Some(specifier) => graph.get_module_with_redirect(&specifier, 0),
+                    None => graph.global_package_imports.get(&import).cloned(),
+                }?;
+                Some((import, module))
+            })
+            .collect::<HashMap<_, _>>();
+
+        ESMGraphModule::detached(specifier, self.path.clone(), imports)
+    }
+}
+
+/// Module of an external script, bare `src` are resolved with the imports of the local modules
+fn script_module(
+    graph: &ModuleGraph,
+    src: &str,
+    specifier: Option<&ModuleSpecifier>,
+) -> Option<GraphModule> {
+    specifier
+        .and_then(|specifier| graph.get_module_with_redirect(specifier, 0))
+        .or_else(|| graph.global_package_imports.get(src).cloned())
+}
+
+/// Transforms the modules reachable from `roots` into the files the pages of the `html` command load
+/// (`ModuleUrls::Static`). npm packages are loaded from esm.sh and not written. Returns the number of files.
+pub async fn write_static_modules(
+    graph: Arc<ModuleGraph>,
+    roots: Vec<GraphModule>,
+    transpiler: &TransformPool,
+    target: &Target,
+    source_map: SourceMapOptions,
+    out_dir: &Path,
+) -> Result<usize, String> {
+    let mut modules = graph
+        .walk_from(roots, None)
+        .into_iter()
+        .filter_map(|module| module.esm())
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    let concurrency = transpiler.threads();
+    let mut tasks = JoinSet::new();
+    let mut written = 0;
+
+    loop {
+        while tasks.len() < concurrency {
+            let Some(module) = modules.next() else {
+                break;
+            };
+            tasks.spawn(write_static_module(
+                graph.clone(),
+                module,
+                transpiler.clone(),
+                target.clone(),
+                source_map,
+                out_dir.to_path_buf(),
+            ));
+        }
+
+        let Some(result) = tasks.join_next().await else {
+            break;
+        };
+
+        match result {
+            Ok(Ok(files)) => written += files,
+            Ok(Err(err)) => {
+                tasks.abort_all();
+                return Err(err);
+            }
+            Err(err) => {
+                tasks.abort_all();
+                return Err(format!("Failed to write module: {}", err));
+            }
+        }
+    }
+
+    Ok(written)
+}
+
+async fn write_static_module(
+    graph: Arc<ModuleGraph>,
+    module: Arc<ESMGraphModule>,
+    transpiler: TransformPool,
+    target: Target,
+    source_map: SourceMapOptions,
+    out_dir: PathBuf,
+) -> Result<usize, String> {
+    let specifier = module.specifier();
+    let segments = static_path(&graph, &GraphModule::Esm(module.clone()))
+        .ok_or_else(|| format!("No output path for {}", specifier))?;
+    let out_path = segments
+        .iter()
+        .fold(out_dir, |path, segment| path.join(segment));
+
+    let code = module
+        .load_code()
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", specifier, e))?;
+
+    if let Some(lockfile) = &graph.lockfile {
+        lockfile
+            .verify_module(&specifier, code.as_bytes())
+            .map_err(|e| e.to_string())?;
+        lockfile
+            .verify_jsr_module(&specifier, module.local(), code.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let result = transpiler
+        .schedule(
+            TransformOptions {
+                code,
+                hmr: false,
+                graph: graph.clone(),
+                module,
+                source_map,
+                target,
+                urls: ModuleUrls::Static,
+            },
+            Priority::Normal,
+        )
+        .await
+        .result()
+        .await
+        .ok_or_else(|| format!("Failed to transform {}", specifier))?;
+
+    if let Some(parent) = out_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    tokio::fs::write(&out_path, result.code)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", out_path.display(), e))?;
+
+    let Some(map) = result.source_map else {
+        return Ok(1);
+    };
+    let mut map_path = out_path.into_os_string();
+    map_path.push(".map");
+    let map_path = PathBuf::from(map_path);
+    tokio::fs::write(&map_path, map)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", map_path.display(), e))?;
+
+    Ok(2)
+}
+
+/// Where the `html` command writes `page`, pages outside of the project are rejected (they would end up
+/// outside of `out_dir`)
+pub fn page_out_path(page: &Path, root_dir: &Path, out_dir: &Path) -> Result<PathBuf, String> {
+    let page = page.clean();
+    let relative = page
+        .strip_prefix(root_dir.clean())
+        .map_err(|_| format!("{} is outside of the project", page.display()))?;
+
+    if relative.as_os_str().is_empty() {
+        return Err(format!("{} is not a page", page.display()));
+    }
+
+    Ok(out_dir.join(relative))
+}
+
+/// Resolves a script `src` or import like the browser would, `/` is the project root.
+/// `None` for bare specifiers.
+pub fn resolve(
+    specifier: &str,
+    page: &ModuleSpecifier,
+    root_dir: &Path,
+) -> Option<ModuleSpecifier> {
+    if specifier.starts_with('/') && !specifier.starts_with("//") {
+        return ModuleSpecifier::from_file_path(root_dir.join(&specifier[1..])).ok();
+    }
+
+    if specifier.starts_with("./") || specifier.starts_with("../") || specifier.starts_with("//") {
+        return page.join(specifier).ok();
+    }
+
+    ModuleSpecifier::parse(specifier).ok()
+}
+
+/// `src` (`None` for inline scripts) and inline code of the module scripts in document order
+fn discover_scripts(html: &str) -> Result<Vec<(Option<String>, String)>, String> {
+    let scripts = RefCell::new(Vec::<(Option<String>, String)>::new());
+
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(MODULE_SCRIPT, |el| {
+                    scripts
+                        .borrow_mut()
+                        .push((el.get_attribute("src"), String::new()));
+                    Ok(())
+                }),
+                text!(MODULE_SCRIPT, |chunk| {
+                    if let Some((None, code)) = scripts.borrow_mut().last_mut() {
+                        code.push_str(chunk.as_str());
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(scripts.into_inner())
+}
+
+/// Roots first, then their transitive imports
+fn preload_urls(
+    graph: &ModuleGraph,
+    roots: &[Arc<ModuleSpecifier>],
+    urls: ModuleUrls,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut preload = Vec::new();
+
+    for root in roots {
+        let modules = graph
+            .get_module_with_redirect(root, 0)
+            .into_iter()
+            .chain(graph.preload(root, PRELOAD_LIMIT));
+
+        for module in modules {
+            if preload.len() >= PRELOAD_LIMIT {
+                return preload;
+            }
+            if matches!(module, GraphModule::Virtual(_)) || !seen.insert(module.specifier()) {
+                continue;
+            }
+            preload.push(urls.url(graph, &module));
+        }
+    }
+
+    preload
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[test]
+fn test_discover_scripts() {
+    let scripts = discover_scripts(
+        r#"<html><head>
+            <script type="module" src="./main.tsx"></script>
+            <script src="legacy.js"></script>
+            <script type="module">import "./inline.ts";</script>
+        </head></html>"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        scripts,
+        vec![
+            (Some("./main.tsx".to_string()), String::new()),
+            (None, "import \"./inline.ts\";".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_resolve() {
+    let page = ModuleSpecifier::parse("file:///project/pages/index.html").unwrap();
+    let root_dir = Path::new("/project");
+
+    assert_eq!(
+        resolve("./main.tsx", &page, root_dir).unwrap().as_str(),
+        "file:///project/pages/main.tsx"
+    );
+    assert_eq!(
+        resolve("../lib.ts", &page, root_dir).unwrap().as_str(),
+        "file:///project/lib.ts"
+    );
+    assert_eq!(
+        resolve("https://esm.sh/preact", &page, root_dir)
+            .unwrap()
+            .as_str(),
+        "https://esm.sh/preact"
+    );
+    assert_eq!(resolve("preact", &page, root_dir), None);
+}
+
+#[test]
+fn test_page_out_path() {
+    let root_dir = Path::new("/project");
+    let out_dir = Path::new("/project/dist");
+
+    assert_eq!(
+        page_out_path(Path::new("/project/pages/index.html"), root_dir, out_dir).unwrap(),
+        PathBuf::from("/project/dist/pages/index.html")
+    );
+    assert_eq!(
+        page_out_path(Path::new("/project/pages/../index.html"), root_dir, out_dir).unwrap(),
+        PathBuf::from("/project/dist/index.html")
+    );
+    assert!(page_out_path(Path::new("/project/../index.html"), root_dir, out_dir).is_err());
+    assert!(page_out_path(Path::new("/etc/index.html"), root_dir, out_dir).is_err());
+    assert!(page_out_path(Path::new("/project"), root_dir, out_dir).is_err());
+}
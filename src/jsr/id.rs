@@ -0,0 +1,100 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::specifier::ModuleSpecifier;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JsrPackageId {
+    /// Scoped name (ex. "@std/path")
+    pub name: String,
+    pub version: String,
+}
+
+impl JsrPackageId {
+    pub fn from_string(id: &str) -> Option<Self> {
+        if !id.starts_with('@') {
+            return None;
+        }
+        let at_pos = id[1..].find('@')?;
+        Some(Self {
+            name: id[..at_pos + 1].to_string(),
+            version: id[(at_pos + 2)..].to_string(),
+        })
+    }
+
+    /// Splits `https://jsr.io/@scope/name/1.0.0/mod.ts` into the package id and `mod.ts`
+    pub fn from_url(specifier: &ModuleSpecifier) -> Option<(Self, String)> {
+        if specifier.host_str() != Some("jsr.io") {
+            return None;
+        }
+
+        let mut parts = specifier.path_segments()?;
+        let scope = parts.next()?;
+        let name = parts.next()?;
+        let version = parts.next()?;
+
+        if !scope.starts_with('@') {
+            return None;
+        }
+
+        Some((
+            Self {
+                name: format!("{}/{}", scope, name),
+                version: version.to_string(),
+            },
+            parts.collect::<Vec<_>>().join("/"),
+        ))
+    }
+
+    /// Splits a jsr specifier (`jsr:@scope/name@^1.0.0/sub` or `jsr:/@scope/name@^1.0.0/sub`)
+    /// into the package name and the export subpath (`.` or `./sub`)
+    pub fn parse_specifier(specifier: &str) -> Option<(String, String)> {
+        let rest = specifier.strip_prefix("jsr:")?.trim_start_matches('/');
+        let (scope, rest) = rest.split_once('/')?;
+        let end = rest.find(['@', '/']).unwrap_or(rest.len());
+        let (name, rest) = rest.split_at(end);
+        let subpath = match rest.strip_prefix('@') {
+            // skip the version requirement
+            Some(rest) => rest.split_once('/').map(|(_, sub)| sub).unwrap_or(""),
+            None => rest.trim_start_matches('/'),
+        };
+
+        Some((
+            format!("{}/{}", scope, name),
+            if subpath.is_empty() {
+                ".".to_string()
+            } else {
+                format!("./{}", subpath)
+            },
+        ))
+    }
+}
+
+impl Display for JsrPackageId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+#[test]
+fn test_jsr_package_id() {
+    let id = JsrPackageId::from_string("@std/path@1.0.8").unwrap();
+    assert_eq!(id.name, "@std/path");
+    assert_eq!(id.version, "1.0.8");
+
+    let url = ModuleSpecifier::parse("https://jsr.io/@std/path/1.0.8/posix/join.ts").unwrap();
+    assert_eq!(
+        JsrPackageId::from_url(&url),
+        Some((id, "posix/join.ts".to_string()))
+    );
+
+    assert_eq!(
+        JsrPackageId::parse_specifier("jsr:/@std/assert@^1.0.11/assert"),
+        Some(("@std/assert".to_string(), "./assert".to_string()))
+    );
+    assert_eq!(
+        JsrPackageId::parse_specifier("jsr:@deno-plc/nats@^0.3.4"),
+        Some(("@deno-plc/nats".to_string(), ".".to_string()))
+    );
+}
@@ -0,0 +1,16 @@
+pub mod analyze;
+pub mod api;
+pub mod config;
+pub mod deno;
+pub mod graph;
+pub mod html;
+pub mod jsr;
+pub mod logging;
+pub mod metrics;
+pub mod npm;
+pub mod server;
+pub mod specifier;
+pub mod transpiler;
+pub mod vendor;
+
+pub use server::{DevServer, ServerHandle};
@@ -0,0 +1,43 @@
+//! Log output on stderr (stdout is reserved for the ready handshake and command output).
+//!
+//! Targets: `graph`, `npm`, `transpiler`, `api`, `analyze`, `vendor`. The JSON format writes one object per
+//! line (`timestamp`, `level`, `target`, `message` and the event fields) for the logtape sink of the launcher.
+//!
+//! Module requests, transforms and graph builds run in `debug` spans, their timings are logged when the span
+//! closes (`DENO_PLC_BUILD_LOG=debug`).
+
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
+
+/// Overrides `Config::log_level`
+pub const LOG_ENV: &str = "DENO_PLC_BUILD_LOG";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Installs the global subscriber, does nothing if the embedding program already installed one
+pub fn init(level: Option<&str>, format: LogFormat) {
+    let filter = EnvFilter::try_from_env(LOG_ENV)
+        .or_else(|_| EnvFilter::try_new(level.unwrap_or("info")))
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid log filter: {}", err);
+            EnvFilter::new("info")
+        });
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().flatten_event(true).try_init(),
+    };
+
+    result.ok();
+}
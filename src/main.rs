@@ -1,66 +1,397 @@
-pub mod api;
-mod config;
-pub mod deno;
-pub mod graph;
-pub mod npm;
-pub mod specifier;
-pub mod transpiler;
 use std::{
-    net::{IpAddr, Ipv6Addr, SocketAddr},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use api::router;
-use config::Config;
-use deno::info::call_deno_info;
-use graph::ModuleGraph;
-use tokio::{signal, spawn};
+use deno_plc_build::{
+    DevServer, ServerHandle,
+    analyze::size::analyze_sizes,
+    api::GraphStatus,
+    config::{self, Command, Config},
+    deno::info::{DenoInfo, call_deno_dirs},
+    graph::{
+        ModuleGraph,
+        cycles::{ImportCycle, new_cycles},
+    },
+    html::{HtmlPage, page_out_path, write_static_modules},
+    logging,
+    server::{
+        ListenAddr,
+        instance::{InstanceInfo, InstanceLock},
+        shutdown_signal,
+        watchdog::launcher_exited,
+    },
+    transpiler::{TransformPool, transform::ModuleUrls},
+    vendor,
+};
+use serde_json::json;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
-    let config: Config = config::from_args();
+    let (config, command): (Config, Command) = config::from_args();
 
-    let root_dir = PathBuf::from(config.root_path);
+    logging::init(config.log_level.as_deref(), config.log_format);
 
-    let mut graph = ModuleGraph::new();
+    info!(target: "graph", "root_path = {}", config.root_path);
 
-    println!("Retrieving graph");
+    // watched from the start, building the graph can take a while
+    let mut watchdog = tokio::spawn(launcher_exited(config.parent_pid, config.watch_stdin));
 
-    let info = match call_deno_info("deno", &root_dir, &config.root_module).await {
-        Ok(info) => info,
-        Err(err) => {
-            eprintln!("Error retrieving graph:\n{}", err);
-            return;
+    let root_dir = PathBuf::from(&config.root_path);
+    let vendor_dir = config.vendor_dir();
+
+    let server = DevServer::new(config.clone()).use_cached_graph(command != Command::Vendor);
+
+    // the server answers /healthz and /readyz while the graph is built
+    let handle = if command == Command::Serve {
+        match server.listen().await {
+            Ok(handle) => {
+                info!(target: "api", "Graph server listening on {}", handle.local_addr());
+                Some(handle)
+            }
+            Err(err) => {
+                let err = match running_instance(&config).await {
+                    Some(instance) => format!(
+                        "{}\n{} is still running, it may be orphaned (stop it with `kill {}`)",
+                        err, instance, instance.pid
+                    ),
+                    None => err,
+                };
+                println!("{}", json!({ "event": "error", "error": err }));
+                fail(None, err).await
+            }
         }
+    } else {
+        None
+    };
+
+    let instance_lock = match &handle {
+        Some(handle) => match InstanceLock::acquire(&config, handle.local_addr()).await {
+            Ok((lock, stale)) => {
+                if let Some(stale) = stale {
+                    warn!(target: "api", "Replaced stale lockfile of {}", stale);
+                }
+                Some(lock)
+            }
+            Err(err) => {
+                warn!(target: "api", "{}", err);
+                None
+            }
+        },
+        None => None,
     };
 
-    println!("Processing graph");
+    let build = async {
+        let mut graph = server.prepare_graph().await?;
+
+        let entrypoints = server.entrypoints().await?;
+        for (name, specifier) in &entrypoints {
+            info!(target: "graph", "entrypoint {} = {}", name, specifier);
+        }
+
+        info!(target: "graph", "Retrieving graph");
+
+        let info = server
+            .load_info(&graph, &entrypoints)
+            .await
+            .map_err(|err| format!("Error retrieving graph:\n{}", err))?;
+
+        info!(target: "graph", "Processing graph");
 
-    graph.build(info, root_dir).await;
+        let vendor_info = (command == Command::Vendor).then(|| info.clone());
+
+        graph.build(info, &entrypoints, root_dir).await;
+
+        Ok::<_, String>((graph, vendor_info))
+    };
+
+    let (graph, vendor_info) = tokio::select! {
+        result = build => match result {
+            Ok(built) => built,
+            Err(err) => fail(handle, err).await,
+        },
+        _ = &mut watchdog => {
+            info!(target: "api", "Launcher exited while the graph was built");
+            if let Some(handle) = handle {
+                handle.shutdown().await.ok();
+            }
+            if let Some(lock) = instance_lock {
+                lock.release().await;
+            }
+            return;
+        }
+    };
 
     let graph = Arc::new(graph);
 
-    println!("Graph built");
+    info!(target: "graph", "Graph built");
 
-    let listener = tokio::net::TcpListener::bind(SocketAddr::new(
-        IpAddr::V6(Ipv6Addr::LOCALHOST),
-        config.port,
-    ))
-    .await
-    .unwrap();
+    match command {
+        Command::Serve => {}
+        Command::Cycles => {
+            report_cycles(&graph, &config).await;
+            return;
+        }
+        Command::Vendor => {
+            vendor_graph(&graph, vendor_info.unwrap(), &vendor_dir).await;
+            return;
+        }
+        Command::Size { out_dir } => {
+            let out_dir = out_dir.unwrap_or_else(|| graph.root_dir.clone());
+            write_size_report(graph, &config, out_dir).await;
+            return;
+        }
+        Command::Html { out_dir } => {
+            let out_dir = out_dir.unwrap_or_else(|| graph.root_dir.join("dist"));
+            write_html(graph, &config, &out_dir).await;
+            return;
+        }
+    }
+
+    let handle = handle.unwrap();
+    handle.state().swap_graph(graph.clone());
+    if config.warm_up {
+        handle.state().spawn_warm_up();
+    }
+
+    // handshake for the launcher (src/graph/server.ts), which can't connect to a Unix socket
+    let (url, socket) = match handle.local_addr() {
+        ListenAddr::Unix(path) => (None, Some(path.display().to_string())),
+        addr => (Some(addr.url()), None),
+    };
+    println!(
+        "{}",
+        json!({
+            "event": "ready",
+            "port": handle.local_addr().port(),
+            "url": url,
+            "socket": socket,
+            // the launcher trusts the generated certificate for its requests to the API
+            "ca": handle.self_signed_cert().map(|cert| cert.display().to_string()),
+            "modules": graph.modules().count(),
+        })
+    );
+
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        _ = &mut watchdog => {
+            info!(target: "api", "Launcher exited");
+        }
+    }
 
-    spawn(async move {
-        println!("Graph server listening on http://localhost:{}", config.port);
-        axum::serve(listener, router(graph)).await.unwrap();
-    });
+    info!(target: "api", "Shutting down");
 
-    match signal::ctrl_c().await {
-        Ok(()) => {}
+    if let Err(err) = handle.shutdown().await {
+        error!(target: "api", "{}", err);
+    }
+
+    if let Some(lock) = instance_lock {
+        lock.release().await;
+    }
+}
+
+/// The instance recorded for the configured address, if its lockfile exists
+async fn running_instance(config: &Config) -> Option<InstanceInfo> {
+    let path = InstanceLock::path_for_config(config)?;
+    InstanceLock::read(&path).await
+}
+
+/// Reports the error (as handshake if serving) and exits
+async fn fail(handle: Option<ServerHandle>, error: String) -> ! {
+    error!(target: "api", "{}", error);
+
+    if let Some(handle) = handle {
+        handle.state().set_status(GraphStatus::Failed {
+            error: error.clone(),
+        });
+        println!("{}", json!({ "event": "error", "error": error }));
+        handle.shutdown().await.ok();
+    }
+
+    std::process::exit(1);
+}
+
+async fn report_cycles(graph: &ModuleGraph, config: &Config) {
+    let cycles = graph.find_cycles();
+
+    let baseline: Vec<ImportCycle> = match &config.cycle_baseline {
+        Some(path) => match tokio::fs::read_to_string(path).await {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(baseline) => baseline,
+                Err(err) => {
+                    error!(target: "graph", "Invalid cycle baseline {}: {}", path, err);
+                    std::process::exit(2);
+                }
+            },
+            Err(err) => {
+                error!(target: "graph", "Failed to read cycle baseline {}: {}", path, err);
+                std::process::exit(2);
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let new = new_cycles(&cycles, &baseline);
+
+    // command output
+    println!("{}", serde_json::to_string_pretty(&cycles).unwrap());
+
+    for cycle in &new {
+        warn!(
+            target: "graph",
+            "New import cycle: {}",
+            cycle
+                .modules
+                .iter()
+                .map(|module| module.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        );
+    }
+
+    info!(target: "graph",
+        "Found {} import cycles ({} not in baseline)",
+        cycles.len(),
+        new.len()
+    );
+
+    if config.fail_on_new_cycles && !new.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+async fn write_size_report(graph: Arc<ModuleGraph>, config: &Config, out_dir: PathBuf) {
+    let npm_cache = match call_deno_dirs("deno", &graph.root_dir).await {
+        Ok(dirs) => Some(dirs.npm_cache),
+        Err(err) => {
+            warn!(
+                target: "analyze",
+                "Unable to locate npm cache, npm packages are skipped: {}",
+                err
+            );
+            None
+        }
+    };
+
+    info!(target: "analyze", "Analyzing module sizes");
+
+    let report = analyze_sizes(
+        graph,
+        npm_cache,
+        &config.target,
+        &TransformPool::from_config(config),
+    )
+    .await;
+
+    for error in &report.errors {
+        warn!(target: "analyze", "{}", error);
+    }
+
+    let json_path = out_dir.join("size-report.json");
+    let html_path = out_dir.join("size-report.html");
+
+    if let Err(err) =
+        tokio::fs::write(&json_path, serde_json::to_string_pretty(&report).unwrap()).await
+    {
+        error!(target: "analyze", "Failed to write {}: {}", json_path.display(), err);
+        std::process::exit(1);
+    }
+
+    if let Err(err) = tokio::fs::write(&html_path, report.to_html()).await {
+        error!(target: "analyze", "Failed to write {}: {}", html_path.display(), err);
+        std::process::exit(1);
+    }
+
+    info!(target: "analyze",
+        "Size report written to {} ({} total, brotli)",
+        html_path.display(),
+        report.total.brotli
+    );
+}
+
+/// Writes the pages and the transformed modules they load as a static site, npm packages are loaded from esm.sh
+async fn write_html(graph: Arc<ModuleGraph>, config: &Config, out_dir: &Path) {
+    let transpiler = TransformPool::from_config(config);
+    let mut roots = Vec::new();
+
+    for path in config.html_entrypoints() {
+        let result = async {
+            let out_path = page_out_path(&path, &graph.root_dir, out_dir)?;
+            let page = HtmlPage::load(&path, &graph.root_dir).await?;
+            roots.extend(page.modules(&graph));
+            let html = page
+                .render(
+                    graph.clone(),
+                    &transpiler,
+                    &config.target,
+                    false,
+                    ModuleUrls::Static,
+                )
+                .await?;
+
+            if let Some(parent) = out_path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            tokio::fs::write(&out_path, html)
+                .await
+                .map_err(|e| format!("Failed to write {}: {}", out_path.display(), e))?;
+
+            Ok::<_, String>(out_path)
+        }
+        .await;
+
+        match result {
+            Ok(out_path) => info!(target: "graph", "Wrote {}", out_path.display()),
+            Err(err) => {
+                error!(target: "graph", "{}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match write_static_modules(
+        graph.clone(),
+        roots,
+        &transpiler,
+        &config.target,
+        config.source_maps,
+        out_dir,
+    )
+    .await
+    {
+        Ok(files) => {
+            info!(target: "graph", "Wrote {} module files to {}", files, out_dir.display())
+        }
         Err(err) => {
-            eprintln!("Unable to listen for shutdown signal: {}", err);
+            error!(target: "graph", "{}", err);
+            std::process::exit(1);
         }
     }
+}
 
-    println!("Shutting down");
+async fn vendor_graph(graph: &ModuleGraph, info: DenoInfo, vendor_dir: &Path) {
+    let npm_cache = match call_deno_dirs("deno", &graph.root_dir).await {
+        Ok(dirs) => Some(dirs.npm_cache),
+        Err(err) => {
+            warn!(target: "vendor", "Unable to locate npm cache: {}", err);
+            None
+        }
+    };
+
+    info!(target: "vendor", "Vendoring into {}", vendor_dir.display());
+
+    match vendor::vendor(graph, info, npm_cache.as_deref(), vendor_dir).await {
+        Ok(manifest) => info!(target: "vendor",
+            "Vendored {} modules and {} npm packages",
+            manifest.modules.len(),
+            manifest.npm_packages.len()
+        ),
+        Err(err) => {
+            error!(target: "vendor", "Vendoring failed: {}", err);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,282 @@
+//! Process-wide request counters and latency histograms, rendered in the Prometheus text format on `/metrics`.
+//!
+//! The metrics are shared by all routers of the process, nested projects are not distinguished.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+const PREFIX: &str = "deno_plc_build";
+
+/// Buckets (seconds) for per-module work
+const MODULE_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Buckets (seconds) for building the whole graph
+const GRAPH_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: &'static [f64],
+    data: Mutex<HistogramData>,
+}
+
+#[derive(Debug, Default)]
+struct HistogramData {
+    /// Observations per bucket (not cumulative), the last one is `+Inf`
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            data: Mutex::new(HistogramData {
+                counts: vec![0; buckets.len() + 1],
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(self.buckets.len());
+
+        let mut data = self.data.lock().unwrap();
+        data.counts[bucket] += 1;
+        data.sum += seconds;
+        data.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let data = self.data.lock().unwrap();
+
+        writeln!(out, "# HELP {PREFIX}_{name} {help}").unwrap();
+        writeln!(out, "# TYPE {PREFIX}_{name} histogram").unwrap();
+
+        let mut cumulative = 0;
+        for (bound, count) in self.buckets.iter().zip(&data.counts) {
+            cumulative += count;
+            writeln!(out, "{PREFIX}_{name}_bucket{{le=\"{bound}\"}} {cumulative}").unwrap();
+        }
+        writeln!(out, "{PREFIX}_{name}_bucket{{le=\"+Inf\"}} {}", data.count).unwrap();
+        writeln!(out, "{PREFIX}_{name}_sum {}", data.sum).unwrap();
+        writeln!(out, "{PREFIX}_{name}_count {}", data.count).unwrap();
+    }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    /// (route, status) -> count
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    /// Reading a module from disk or the deno cache
+    pub module_load: Histogram,
+    /// Time a transform waited for a free worker
+    pub transform_queue: Histogram,
+    /// Parsing, transforming and emitting a module with swc
+    pub transform: Histogram,
+    pub graph_build: Histogram,
+    pub transform_cache_hits: Counter,
+    pub transform_cache_misses: Counter,
+    pub transform_cache_evictions: Counter,
+    pub transform_failures: Counter,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(|| Metrics {
+    requests: Default::default(),
+    module_load: Histogram::new(MODULE_BUCKETS),
+    transform_queue: Histogram::new(MODULE_BUCKETS),
+    transform: Histogram::new(MODULE_BUCKETS),
+    graph_build: Histogram::new(GRAPH_BUCKETS),
+    transform_cache_hits: Default::default(),
+    transform_cache_misses: Default::default(),
+    transform_cache_evictions: Default::default(),
+    transform_failures: Default::default(),
+});
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Values that are sampled when the metrics are rendered
+#[derive(Debug, Default)]
+pub struct Gauges {
+    pub transform_queue_depth: usize,
+    pub transform_waiting: usize,
+    pub transform_active: usize,
+    pub transform_cache_bytes: usize,
+    pub modules: usize,
+}
+
+impl Metrics {
+    pub fn record_request(&self, route: &str, status: u16) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((route.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// Prometheus text exposition format
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "# HELP {PREFIX}_http_requests_total HTTP requests by route and status"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE {PREFIX}_http_requests_total counter").unwrap();
+        for ((route, status), count) in self.requests.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "{PREFIX}_http_requests_total{{route=\"{}\",status=\"{}\"}} {}",
+                escape_label(route),
+                status,
+                count
+            )
+            .unwrap();
+        }
+
+        self.module_load.render(
+            &mut out,
+            "module_load_duration_seconds",
+            "Time to read a module before it is transformed",
+        );
+        self.transform_queue.render(
+            &mut out,
+            "transform_queue_duration_seconds",
+            "Time a transform waited for a worker thread",
+        );
+        self.transform.render(
+            &mut out,
+            "transform_duration_seconds",
+            "Time to transform a module with swc",
+        );
+        self.graph_build.render(
+            &mut out,
+            "graph_build_duration_seconds",
+            "Time to build the module graph from the deno info output",
+        );
+
+        counter(
+            &mut out,
+            "transform_cache_hits_total",
+            "Transforms served from the cache",
+            self.transform_cache_hits.get(),
+        );
+        counter(
+            &mut out,
+            "transform_cache_misses_total",
+            "Transforms that had to run swc",
+            self.transform_cache_misses.get(),
+        );
+        counter(
+            &mut out,
+            "transform_cache_evictions_total",
+            "Transforms evicted from the full cache",
+            self.transform_cache_evictions.get(),
+        );
+        counter(
+            &mut out,
+            "transform_failures_total",
+            "Transforms that failed",
+            self.transform_failures.get(),
+        );
+
+        gauge(
+            &mut out,
+            "transform_queue_depth",
+            "Transforms waiting for a worker thread",
+            gauges.transform_queue_depth,
+        );
+        gauge(
+            &mut out,
+            "transform_waiting",
+            "Requests waiting for room in the transform queue",
+            gauges.transform_waiting,
+        );
+        gauge(
+            &mut out,
+            "transform_active",
+            "Transforms currently running",
+            gauges.transform_active,
+        );
+        gauge(
+            &mut out,
+            "transform_cache_bytes",
+            "Size of the cached transforms",
+            gauges.transform_cache_bytes,
+        );
+        gauge(
+            &mut out,
+            "graph_modules",
+            "Modules in the current graph",
+            gauges.modules,
+        );
+
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {PREFIX}_{name} {help}").unwrap();
+    writeln!(out, "# TYPE {PREFIX}_{name} counter").unwrap();
+    writeln!(out, "{PREFIX}_{name} {value}").unwrap();
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    writeln!(out, "# HELP {PREFIX}_{name} {help}").unwrap();
+    writeln!(out, "# TYPE {PREFIX}_{name} gauge").unwrap();
+    writeln!(out, "{PREFIX}_{name} {value}").unwrap();
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[test]
+fn test_histogram() {
+    let histogram = Histogram::new(&[0.01, 0.1]);
+    histogram.observe(Duration::from_millis(5));
+    histogram.observe(Duration::from_millis(50));
+    histogram.observe(Duration::from_secs(1));
+
+    let mut out = String::new();
+    histogram.render(&mut out, "test_seconds", "Test");
+
+    assert!(out.contains("deno_plc_build_test_seconds_bucket{le=\"0.01\"} 1\n"));
+    assert!(out.contains("deno_plc_build_test_seconds_bucket{le=\"0.1\"} 2\n"));
+    assert!(out.contains("deno_plc_build_test_seconds_bucket{le=\"+Inf\"} 3\n"));
+    assert!(out.contains("deno_plc_build_test_seconds_count 3\n"));
+}
@@ -20,31 +20,43 @@
 import { format, type SemVer } from "@std/semver";
 import * as esbuild from "esbuild";
 import { join } from "@std/path/join";
+import { dirname } from "@std/path/dirname";
 import { PackageJson } from "./package_json.ts";
 import { toFileUrl } from "@std/path/to-file-url";
 import { assertEquals } from "@std/assert/equals";
 import {
+    fetchNpmDir,
+    fetchNpmTarget,
     fetchPackageMetadata,
     type NPMPackage,
     npmToCanonical,
 } from "./package.ts";
 import type { FullConfig } from "../config.ts";
+import type { GraphApi } from "../graph/server.ts";
 import { assert } from "@std/assert/assert";
-import { deno_info } from "../deno/info.ts";
 import { nodeProbeAddition } from "./probe.ts";
 
 export class NPMCompiler {
-    constructor(readonly config: FullConfig) {}
+    constructor(
+        readonly config: FullConfig,
+        readonly graph_api: Promise<GraphApi>,
+    ) {}
 
     #cache = new Map<string, CompiledNPMPackage>();
     #queue = new Map<string, Promise<CompiledNPMPackage>>();
+    #target: Promise<string[]> | null = null;
 
     async #compile(
         package_name: string,
         version: SemVer,
     ): Promise<CompiledNPMPackage> {
         // console.log(`Compiling npm:${package_name}@${format(version)}`);
-        const { denoDir, npmCache } = await deno_info;
+        const npm_dir = await fetchNpmDir(await this.graph_api);
+        // the entry points are resolved relative to it (`npm/<registry>/...`)
+        const working_dir = dirname(npm_dir);
+        // lowered like the modules transformed by the graph server
+        this.#target ??= this.graph_api.then(fetchNpmTarget);
+        const target = await this.#target;
 
         if (package_name === "util") {
             console.log("compiling util");
@@ -52,7 +64,7 @@ export class NPMCompiler {
         const metadata = await fetchPackageMetadata({
             name: package_name,
             version,
-        });
+        }, await this.graph_api);
         if (package_name === "util") {
             console.log("got util metadata");
         }
@@ -60,7 +72,7 @@ export class NPMCompiler {
         const registry = metadata.registry_url.hostname;
 
         const local_path = join(
-            npmCache,
+            npm_dir,
             registry,
             package_name,
             format(version),
@@ -314,7 +326,11 @@ export class NPMCompiler {
             platform: "neutral",
             metafile: true,
             minify: true,
-            absWorkingDir: denoDir,
+            target,
+            // chained onto the source maps shipped with the package, inline
+            // since the chunks are served from memory
+            sourcemap: this.config.source_maps === "none" ? false : "inline",
+            absWorkingDir: working_dir,
             define: {
                 "process.env.NODE_ENV": JSON.stringify("production"),
                 "process.env.NODE_DEBUG": JSON.stringify(""),
@@ -338,7 +354,7 @@ export class NPMCompiler {
             package_name,
             version,
             res,
-            denoDir,
+            working_dir,
             registry,
             export_paths,
             output_magic,
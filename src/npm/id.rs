@@ -9,10 +9,6 @@ pub struct NPMPackageId {
 }
 
 impl NPMPackageId {
-    pub fn to_string(&self) -> String {
-        format!("{}@{}", self.name, self.version)
-    }
-
     pub fn from_string(id: &str) -> Option<Self> {
         let at_pos = id[1..].find('@');
         if let Some(at_pos) = at_pos {
@@ -52,9 +48,9 @@ impl TryFrom<&str> for NPMPackageId {
     }
 }
 
-impl Into<String> for NPMPackageId {
-    fn into(self) -> String {
-        self.to_string()
+impl From<NPMPackageId> for String {
+    fn from(id: NPMPackageId) -> String {
+        id.to_string()
     }
 }
 
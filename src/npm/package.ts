@@ -21,6 +21,7 @@ import { format } from "@std/semver/format";
 import type { SemVer } from "@std/semver/types";
 import { join } from "@std/path/join";
 import { deno_info } from "../deno/info.ts";
+import { fetchGraphApi, type GraphApi } from "../graph/server.ts";
 import { z } from "zod";
 import { parse as parseSemver } from "@std/semver";
 
@@ -60,11 +61,45 @@ export function parseNPMSpecifier(
     }, path || undefined];
 }
 
-export async function fetchPackageMetadata(pkg: NPMPackage) {
-    const api_url = new URL("http://[::1]:3000/api/v1/npm/metadata");
-    api_url.searchParams.set("name", pkg.name);
-    api_url.searchParams.set("version", format(pkg.version));
-    const response = await fetch(api_url);
+/**
+ * Directory the npm packages are read from: the vendored packages if the graph was loaded from the vendor
+ * directory, otherwise the deno npm cache
+ */
+export async function fetchNpmDir(graph_api: GraphApi): Promise<string> {
+    const response = await fetchGraphApi(graph_api, "/api/v1/npm/dir");
+    if (response.ok) {
+        const { dir } = await response.json();
+        if (dir) {
+            return dir;
+        }
+    } else {
+        await response.body?.cancel();
+    }
+    return (await deno_info).npmCache;
+}
+
+/**
+ * esbuild `target` of the npm packages: the ES version, or the oldest browser
+ * versions of the browserslist query (resolved by the graph server)
+ */
+export async function fetchNpmTarget(graph_api: GraphApi): Promise<string[]> {
+    const response = await fetchGraphApi(graph_api, "/api/v1/npm/target");
+    if (!response.ok) {
+        const { error } = await response.json();
+        throw new Error(`Failed to resolve the npm target: ${error}`);
+    }
+    const { esbuild } = await response.json();
+    return esbuild;
+}
+
+export async function fetchPackageMetadata(
+    pkg: NPMPackage,
+    graph_api: GraphApi,
+) {
+    const response = await fetchGraphApi(graph_api, "/api/v1/npm/metadata", {
+        name: pkg.name,
+        version: format(pkg.version),
+    });
     if (response.ok) {
         const data = await response.json();
         return NPMPackageMetadata.parse(data);
@@ -0,0 +1,115 @@
+//! Lockfile per listen address, to tell which (possibly orphaned) graph server occupies a port.
+//!
+//! `<cache_dir>/instances/<address>.json` is written after binding and removed on shutdown. A lockfile
+//! found on start belongs to a server that is still running (the bind fails) or crashed (stale, replaced).
+
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, deno::lockfile::sha256_hex};
+
+use super::ListenAddr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    pub pid: u32,
+    pub url: String,
+    pub root_path: String,
+    /// Seconds since the unix epoch
+    pub started: u64,
+}
+
+impl std::fmt::Display for InstanceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "graph server (pid {}) for {} at {}",
+            self.pid, self.root_path, self.url
+        )
+    }
+}
+
+pub struct InstanceLock {
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Lockfile of the address the config binds to, `None` for port 0 (only known after binding)
+    pub fn path_for_config(config: &Config) -> Option<PathBuf> {
+        let key = match &config.unix_socket {
+            Some(path) => socket_key(Path::new(path)),
+            None if config.port == 0 => return None,
+            None => format!("port-{}", config.port),
+        };
+        Some(instances_dir(config).join(format!("{}.json", key)))
+    }
+
+    pub fn path_for_addr(config: &Config, addr: &ListenAddr) -> PathBuf {
+        let key = match addr {
+            ListenAddr::Tcp(addr) | ListenAddr::Tls(addr) => format!("port-{}", addr.port()),
+            ListenAddr::Unix(path) => socket_key(path),
+        };
+        instances_dir(config).join(format!("{}.json", key))
+    }
+
+    pub async fn read(path: &Path) -> Option<InstanceInfo> {
+        let content = tokio::fs::read(path).await.ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    /// Records this process as the owner of `addr`, replacing a stale lockfile
+    pub async fn acquire(
+        config: &Config,
+        addr: &ListenAddr,
+    ) -> Result<(InstanceLock, Option<InstanceInfo>), String> {
+        let path = Self::path_for_addr(config, addr);
+        let stale = Self::read(&path).await;
+
+        let info = InstanceInfo {
+            pid: std::process::id(),
+            url: addr.url(),
+            root_path: config.root_path.clone(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        tokio::fs::write(&path, serde_json::to_vec_pretty(&info).unwrap())
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        Ok((InstanceLock { path }, stale))
+    }
+
+    pub async fn release(self) {
+        // another instance may have taken over after we lost the port
+        if Self::read(&self.path)
+            .await
+            .is_some_and(|info| info.pid == std::process::id())
+        {
+            tokio::fs::remove_file(&self.path).await.ok();
+        }
+    }
+}
+
+fn instances_dir(config: &Config) -> PathBuf {
+    config.cache_dir().join("instances")
+}
+
+fn socket_key(path: &Path) -> String {
+    format!(
+        "socket-{}",
+        &sha256_hex(path.to_string_lossy().as_bytes())[..16]
+    )
+}
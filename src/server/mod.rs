@@ -0,0 +1,659 @@
+//! Builds the graph from a `Config` and hosts the API, for embedding the graph server in another program.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+pub mod instance;
+#[cfg(feature = "tls")]
+mod tls;
+pub mod watchdog;
+
+use axum::Router;
+use static_assertions::assert_impl_all;
+use tokio::{sync::oneshot, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{AppState, GraphStatus, TransformCache, router_with_state},
+    config::{Config, GraphBackend, TlsConfig},
+    deno::{
+        info::{DenoInfo, call_deno_dirs},
+        lockfile::Lockfile,
+        workspace::Workspace,
+    },
+    graph::{
+        ModuleGraph,
+        snapshot::SnapshotSource,
+        source::{DenoInfoSource, GraphSource, InfoFileSource, VendorSource},
+    },
+    html::HtmlPage,
+    specifier::ModuleSpecifier,
+    transpiler::TransformPool,
+    vendor,
+};
+
+/// Builder for an in-process graph server
+///
+/// ```ignore
+/// let handle = DevServer::new(config).serve().await?;
+/// let result = handle.state().transform_module(&specifier, true).await?;
+/// handle.shutdown().await?;
+/// ```
+pub struct DevServer {
+    config: Config,
+    source: Option<Box<dyn GraphSource>>,
+    transpiler: Option<TransformPool>,
+    use_cached_graph: bool,
+    drain_timeout: Duration,
+}
+
+assert_impl_all!(DevServer: Send, Sync);
+
+// embedding programs `tokio::spawn` the server, the futures can't be named for `assert_impl_all!`
+const _: fn(DevServer) = |server| {
+    fn assert_send<T: Send>(_: &T) {}
+    assert_send(&server.build_graph());
+    assert_send(&server.serve());
+};
+
+impl DevServer {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            source: None,
+            transpiler: None,
+            use_cached_graph: true,
+            drain_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Load the graph from `source` instead of the configured backend
+    pub fn source(mut self, source: Box<dyn GraphSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Transform modules on `transpiler` instead of the shared pool
+    pub fn transpiler(mut self, transpiler: TransformPool) -> Self {
+        self.transpiler = Some(transpiler);
+        self
+    }
+
+    /// Whether a vendored graph or graph snapshot may be used (default: true)
+    pub fn use_cached_graph(mut self, use_cached_graph: bool) -> Self {
+        self.use_cached_graph = use_cached_graph;
+        self
+    }
+
+    /// How long `ServerHandle::shutdown` waits for requests in flight (default: 10s)
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Empty graph with the workspace and lockfile of the project, ready for `ModuleGraph::build`
+    pub async fn prepare_graph(&self) -> Result<ModuleGraph, String> {
+        let root_dir = PathBuf::from(&self.config.root_path);
+
+        let mut graph = ModuleGraph::new();
+
+        graph.workspace = Workspace::discover(&root_dir).await;
+
+        for member in &graph.workspace.members {
+            info!(target: "api",
+                "Workspace member {} at {} served as {}",
+                member.name,
+                member.path.display(),
+                member.prefix
+            );
+        }
+
+        let lockfile_path = self.config.lockfile();
+        if self.config.lockfile.is_some() || lockfile_path.exists() {
+            graph.lockfile = Some(Lockfile::load(&lockfile_path).await?);
+        }
+
+        let vendor_dir = self.config.vendor_dir();
+        if self.use_cached_graph
+            && self.config.vendor_dir.is_some()
+            && vendor::is_vendored(&vendor_dir)
+        {
+            graph.npm_dir = Some(vendor_dir.join(vendor::NPM_DIR));
+        } else if graph
+            .lockfile
+            .as_ref()
+            .is_some_and(|lockfile| !lockfile.npm.is_empty())
+        {
+            // npm packages are verified against the lockfile
+            match call_deno_dirs("deno", &root_dir).await {
+                Ok(dirs) => graph.npm_dir = Some(dirs.npm_cache),
+                Err(err) => warn!(target: "npm",
+                    "Unable to locate the npm cache, npm packages are not verified: {}",
+                    err
+                ),
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Configured entrypoints and the module scripts of the HTML entrypoints (`index.html#0`, ...)
+    pub async fn entrypoints(&self) -> Result<BTreeMap<String, ModuleSpecifier>, String> {
+        let root_dir = PathBuf::from(&self.config.root_path);
+        let mut entrypoints = self.config.entrypoints();
+
+        for path in self.config.html_entrypoints() {
+            let page = HtmlPage::load(&path, &root_dir).await?;
+            let name = path
+                .strip_prefix(&root_dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+
+            for (index, root) in page.roots(&root_dir).into_iter().enumerate() {
+                entrypoints
+                    .entry(format!("{}#{}", name, index))
+                    .or_insert(root);
+            }
+        }
+
+        Ok(entrypoints)
+    }
+
+    /// Retrieves the `deno info` graph of the entrypoints
+    pub async fn load_info(
+        &self,
+        graph: &ModuleGraph,
+        entrypoints: &BTreeMap<String, ModuleSpecifier>,
+    ) -> Result<DenoInfo, String> {
+        let roots = entrypoints.values().cloned().collect::<Vec<_>>();
+
+        match &self.source {
+            Some(source) => source.load(&roots).await,
+            None => {
+                let source = graph_source(&self.config, graph, self.use_cached_graph)?;
+                source.load(&roots).await
+            }
+        }
+    }
+
+    pub async fn build_graph(&self) -> Result<Arc<ModuleGraph>, String> {
+        let mut graph = self.prepare_graph().await?;
+        // reads the HTML entrypoints
+        let entrypoints = self.entrypoints().await?;
+        let info = self.load_info(&graph, &entrypoints).await?;
+
+        graph
+            .build(info, &entrypoints, PathBuf::from(&self.config.root_path))
+            .await;
+
+        Ok(Arc::new(graph))
+    }
+
+    /// Builds the graph and the state shared by the API routes
+    pub async fn state(&self) -> Result<AppState, String> {
+        let graph = self.build_graph().await?;
+        Ok(self.state_with_graph(graph))
+    }
+
+    pub fn state_with_graph(&self, graph: Arc<ModuleGraph>) -> AppState {
+        let mut state = AppState::new(graph);
+        state.transpiler = match &self.transpiler {
+            Some(transpiler) => transpiler.clone(),
+            None => TransformPool::from_config(&self.config),
+        };
+        state.transforms = TransformCache::from_config(&self.config);
+        state.source_maps = self.config.source_maps;
+        state.target = self.config.target.clone();
+        state
+    }
+
+    /// Builds the graph and returns the API router, to be nested into another axum app
+    pub async fn router(&self) -> Result<Router, String> {
+        Ok(router_with_state(self.state().await?))
+    }
+
+    /// Serves the API while the graph is built. `/readyz` succeeds once it is ready.
+    pub async fn serve(self) -> Result<ServerHandle, String> {
+        let handle = self.listen().await?;
+
+        match self.build_graph().await {
+            Ok(graph) => {
+                handle.state().swap_graph(graph);
+                if self.config.warm_up {
+                    handle.state().spawn_warm_up();
+                }
+                Ok(handle)
+            }
+            Err(err) => {
+                handle
+                    .state()
+                    .set_status(GraphStatus::Failed { error: err.clone() });
+                handle.shutdown().await.ok();
+                Err(err)
+            }
+        }
+    }
+
+    /// Serves the API for an already built graph
+    pub async fn serve_graph(self, graph: Arc<ModuleGraph>) -> Result<ServerHandle, String> {
+        let handle = self.listen().await?;
+        handle.state().swap_graph(graph);
+        Ok(handle)
+    }
+
+    /// Serves the API with an empty graph on the configured address or socket.
+    /// Swap in the graph once it is built.
+    pub async fn listen(&self) -> Result<ServerHandle, String> {
+        let state = self.state_with_graph(Arc::new(ModuleGraph::new()));
+        state.set_status(GraphStatus::Building);
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let router = router_with_state(state.clone());
+
+        let mut self_signed_cert = None;
+        let (local_addr, task) = match &self.config.unix_socket {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                let task = spawn_unix_server(&path, router, shutdown_rx)?;
+                (ListenAddr::Unix(path), task)
+            }
+            None => {
+                let listener = self.bind_tcp().await?;
+                let local_addr = listener
+                    .local_addr()
+                    .map_err(|e| format!("Failed to get local address: {}", e))?;
+
+                match &self.config.tls {
+                    Some(tls) => {
+                        let (task, cert) =
+                            spawn_tls_server(listener, tls, &self.config, router, shutdown_rx)
+                                .await?;
+                        self_signed_cert = cert;
+                        (ListenAddr::Tls(local_addr), task)
+                    }
+                    None => (
+                        ListenAddr::Tcp(local_addr),
+                        spawn_server(listener, router, shutdown_rx),
+                    ),
+                }
+            }
+        };
+
+        Ok(ServerHandle {
+            local_addr,
+            self_signed_cert,
+            state,
+            shutdown,
+            task,
+            drain_timeout: self.drain_timeout,
+        })
+    }
+
+    async fn bind_tcp(&self) -> Result<tokio::net::TcpListener, String> {
+        let port = self.config.port;
+
+        let bind = |ip: IpAddr| async move {
+            tokio::net::TcpListener::bind(SocketAddr::new(ip, port))
+                .await
+                .map_err(|e| format!("Failed to bind {}: {}", SocketAddr::new(ip, port), e))
+        };
+
+        match self.config.bind_address {
+            Some(ip) => bind(ip).await,
+            None => match bind(IpAddr::V6(Ipv6Addr::LOCALHOST)).await {
+                Ok(listener) => Ok(listener),
+                Err(err) => {
+                    warn!(target: "api", "{}, falling back to IPv4", err);
+                    bind(IpAddr::V4(Ipv4Addr::LOCALHOST)).await
+                }
+            },
+        }
+    }
+}
+
+fn spawn_server<L>(
+    listener: L,
+    router: Router,
+    shutdown: oneshot::Receiver<()>,
+) -> JoinHandle<std::io::Result<()>>
+where
+    L: axum::serve::Listener,
+    L::Addr: std::fmt::Debug,
+{
+    tokio::spawn(async move {
+        axum::serve(listener, router)
+            .with_graceful_shutdown(async {
+                shutdown.await.ok();
+            })
+            .await
+    })
+}
+
+#[cfg(feature = "tls")]
+async fn spawn_tls_server(
+    listener: tokio::net::TcpListener,
+    tls: &TlsConfig,
+    config: &Config,
+    router: Router,
+    shutdown: oneshot::Receiver<()>,
+) -> Result<(JoinHandle<std::io::Result<()>>, Option<PathBuf>), String> {
+    let listener = tls::TlsListener::new(
+        listener,
+        tls,
+        &PathBuf::from(&config.root_path),
+        &config.cache_dir(),
+    )
+    .await?;
+    let cert = listener.self_signed_cert().map(PathBuf::from);
+
+    Ok((spawn_server(listener, router, shutdown), cert))
+}
+
+#[cfg(not(feature = "tls"))]
+async fn spawn_tls_server(
+    _listener: tokio::net::TcpListener,
+    _tls: &TlsConfig,
+    _config: &Config,
+    _router: Router,
+    _shutdown: oneshot::Receiver<()>,
+) -> Result<(JoinHandle<std::io::Result<()>>, Option<PathBuf>), String> {
+    Err("HTTPS requires the `tls` feature".to_string())
+}
+
+#[cfg(unix)]
+fn spawn_unix_server(
+    path: &std::path::Path,
+    router: Router,
+    shutdown: oneshot::Receiver<()>,
+) -> Result<JoinHandle<std::io::Result<()>>, String> {
+    // left over from a previous run
+    if path.exists() {
+        std::fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove stale socket {}: {}", path.display(), e))?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| format!("Failed to bind {}: {}", path.display(), e))?;
+
+    Ok(spawn_server(listener, router, shutdown))
+}
+
+#[cfg(not(unix))]
+fn spawn_unix_server(
+    _path: &std::path::Path,
+    _router: Router,
+    _shutdown: oneshot::Receiver<()>,
+) -> Result<JoinHandle<std::io::Result<()>>, String> {
+    Err("Unix domain sockets are not supported on this platform".to_string())
+}
+
+/// Where the server accepts connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Tls(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            ListenAddr::Tcp(addr) | ListenAddr::Tls(addr) => Some(addr.port()),
+            ListenAddr::Unix(_) => None,
+        }
+    }
+
+    /// URL clients should connect to, `unix:<path>` for sockets.
+    /// Unspecified addresses (`0.0.0.0`, `::`) are replaced by the loopback address.
+    pub fn url(&self) -> String {
+        match self {
+            ListenAddr::Tcp(addr) => format!("http://{}", loopback(addr)),
+            ListenAddr::Tls(addr) => format!("https://{}", loopback(addr)),
+            ListenAddr::Unix(path) => format!("unix:{}", path.display()),
+        }
+    }
+}
+
+fn loopback(addr: &SocketAddr) -> SocketAddr {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    SocketAddr::new(ip, addr.port())
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.url())
+    }
+}
+
+/// A running graph server
+pub struct ServerHandle {
+    local_addr: ListenAddr,
+    self_signed_cert: Option<PathBuf>,
+    state: AppState,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<std::io::Result<()>>,
+    drain_timeout: Duration,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> &ListenAddr {
+        &self.local_addr
+    }
+
+    /// Generated certificate of the HTTPS server (PEM), clients have to trust it as a CA
+    pub fn self_signed_cert(&self) -> Option<&Path> {
+        self.self_signed_cert.as_deref()
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub fn graph(&self) -> Arc<ModuleGraph> {
+        self.state.graph()
+    }
+
+    /// Stops accepting connections and waits up to the drain timeout for requests in flight.
+    /// The server is aborted if they don't finish in time.
+    pub async fn shutdown(self) -> Result<(), String> {
+        self.state.set_status(GraphStatus::Draining);
+        self.shutdown.send(()).ok();
+
+        let mut task = self.task;
+        let result = tokio::time::timeout(self.drain_timeout, &mut task).await;
+
+        if result.is_err() {
+            task.abort();
+            // the listener is closed once the task is gone
+            task.await.ok();
+        }
+
+        if let ListenAddr::Unix(path) = &self.local_addr {
+            std::fs::remove_file(path).ok();
+        }
+
+        match result {
+            Ok(Ok(Ok(()))) => Ok(()),
+            Ok(Ok(Err(e))) => Err(format!("Graph server failed: {}", e)),
+            Ok(Err(e)) => Err(format!("Graph server panicked: {}", e)),
+            Err(_) => Err(format!(
+                "Requests still in flight after {:?}, shutting down anyway",
+                self.drain_timeout
+            )),
+        }
+    }
+}
+
+/// Resolves on Ctrl-C or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!(target: "api", "Unable to listen for Ctrl-C: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                error!(target: "api", "Unable to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Selects where the graph is loaded from. A vendored graph takes precedence over the configured backend,
+/// the graph snapshot wraps either.
+pub fn graph_source(
+    config: &Config,
+    graph: &ModuleGraph,
+    use_cached_graph: bool,
+) -> Result<Box<dyn GraphSource>, String> {
+    let root_dir = PathBuf::from(&config.root_path);
+    let vendor_dir = config.vendor_dir();
+
+    let source: Box<dyn GraphSource> =
+        if use_cached_graph && config.vendor_dir.is_some() && vendor::is_vendored(&vendor_dir) {
+            info!(target: "api", "Using vendored graph from {}", vendor_dir.display());
+            Box::new(VendorSource {
+                vendor_dir,
+                root_dir: root_dir.clone(),
+            })
+        } else {
+            match config.graph_backend {
+                GraphBackend::DenoInfo => Box::new(DenoInfoSource {
+                    deno_executable: "deno".to_string(),
+                    dir: root_dir.clone(),
+                }),
+                GraphBackend::InfoFile => {
+                    let info_file = config
+                        .info_file
+                        .as_ref()
+                        .ok_or("The info_file backend requires `info_file` to be set")?;
+                    Box::new(InfoFileSource {
+                        path: root_dir.join(info_file),
+                    })
+                }
+                GraphBackend::Native => native_source(config, graph)?,
+            }
+        };
+
+    Ok(match config.graph_snapshot() {
+        Some(path) if use_cached_graph => Box::new(SnapshotSource {
+            inner: source,
+            path,
+            root_dir,
+        }),
+        _ => source,
+    })
+}
+
+#[cfg(feature = "native")]
+fn native_source(config: &Config, graph: &ModuleGraph) -> Result<Box<dyn GraphSource>, String> {
+    let deno_dir = config
+        .deno_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(crate::deno::native::default_deno_dir)
+        .ok_or("Unable to locate the deno cache directory")?;
+
+    Ok(Box::new(crate::graph::source::NativeSource {
+        deno_dir,
+        config_dir: PathBuf::from(&config.root_path),
+        lockfile: graph.lockfile.is_some().then(|| config.lockfile()),
+    }))
+}
+
+#[cfg(not(feature = "native"))]
+fn native_source(_config: &Config, _graph: &ModuleGraph) -> Result<Box<dyn GraphSource>, String> {
+    Err("The native graph backend requires the `native` feature".to_string())
+}
+
+#[test]
+fn test_listen_url() {
+    let any = ListenAddr::Tcp(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 3000));
+    assert_eq!(any.url(), "http://[::1]:3000");
+
+    let v4 = ListenAddr::Tcp(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 4000));
+    assert_eq!(v4.url(), "http://127.0.0.1:4000");
+}
+
+#[tokio::test]
+async fn test_shutdown_aborts_after_drain_timeout() {
+    use axum::routing::get;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    let config: Config = serde_json::from_value(serde_json::json!({
+        "root_path": ".",
+        "port": 0,
+    }))
+    .unwrap();
+
+    // a route that accepts the request but never responds
+    let router = Router::new().route("/pending", get(std::future::pending::<()>));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown, shutdown_rx) = oneshot::channel();
+
+    let handle = ServerHandle {
+        local_addr: ListenAddr::Tcp(addr),
+        self_signed_cert: None,
+        state: DevServer::new(config).state_with_graph(Arc::new(ModuleGraph::new())),
+        shutdown,
+        task: spawn_server(listener, router, shutdown_rx),
+        drain_timeout: Duration::from_millis(100),
+    };
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(b"GET /pending HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    // the request is only in flight once the server has read it
+    let mut buf = [0; 1];
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), client.read(&mut buf))
+            .await
+            .is_err()
+    );
+
+    let err = handle.shutdown().await.unwrap_err();
+    assert_eq!(
+        err,
+        "Requests still in flight after 100ms, shutting down anyway"
+    );
+    assert!(TcpStream::connect(addr).await.is_err());
+}
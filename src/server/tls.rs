@@ -0,0 +1,262 @@
+//! HTTPS for the API, either with a user-provided certificate or a self-signed one cached on disk.
+
+use std::{
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::AbortHandle,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer},
+    },
+    server::TlsStream,
+};
+use tracing::{debug, info};
+
+use crate::{config::TlsConfig, deno::lockfile::sha256_hex};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accepts TCP connections and hands them to axum once the TLS handshake is complete.
+/// Each handshake runs in its own task, so a slow client doesn't hold up the others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    /// Generated certificate, `None` for a user-provided one
+    self_signed: Option<PathBuf>,
+    established: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    accept_task: AbortHandle,
+}
+
+impl TlsListener {
+    pub async fn new(
+        tcp: TcpListener,
+        config: &TlsConfig,
+        root_dir: &Path,
+        cache_dir: &Path,
+    ) -> Result<TlsListener, String> {
+        let (cert, key, self_signed) = match (&config.cert, &config.key) {
+            (Some(cert), Some(key)) => (root_dir.join(cert), root_dir.join(key), false),
+            _ => {
+                let (cert, key) = self_signed(&config.hosts, cache_dir).await?;
+                (cert, key, true)
+            }
+        };
+
+        let certs = read_certs(&cert).await?;
+        let key = read_key(&key).await?;
+
+        let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Invalid TLS configuration: {}", e))?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid TLS certificate {}: {}", cert.display(), e))?;
+
+        let local_addr = tcp
+            .local_addr()
+            .map_err(|e| format!("Failed to get local address: {}", e))?;
+        let (tx, established) = mpsc::channel(64);
+        let accept_task = tokio::spawn(accept_loop(
+            tcp,
+            TlsAcceptor::from(Arc::new(server_config)),
+            tx,
+        ))
+        .abort_handle();
+
+        Ok(TlsListener {
+            local_addr,
+            self_signed: self_signed.then_some(cert),
+            established,
+            accept_task,
+        })
+    }
+
+    /// Path of the generated certificate, clients have to trust it as a CA
+    pub fn self_signed_cert(&self) -> Option<&Path> {
+        self.self_signed.as_deref()
+    }
+}
+
+async fn accept_loop(
+    mut tcp: TcpListener,
+    acceptor: TlsAcceptor,
+    established: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, addr) = axum::serve::Listener::accept(&mut tcp).await;
+
+        let acceptor = acceptor.clone();
+        let established = established.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    established.send((stream, addr)).await.ok();
+                }
+                Ok(Err(err)) => {
+                    debug!(target: "api", "TLS handshake with {} failed: {}", addr, err)
+                }
+                Err(_) => debug!(target: "api", "TLS handshake with {} timed out", addr),
+            }
+        });
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.established.recv().await {
+            Some(connection) => connection,
+            // the accept loop never returns, it only stops when the listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Generates a certificate for `localhost` and `hosts`, or reuses the one generated for the same hosts before.
+/// Returns the paths of the certificate and key.
+async fn self_signed(hosts: &[String], cache_dir: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let mut names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+    for host in hosts {
+        if !names.contains(host) {
+            names.push(host.clone());
+        }
+    }
+
+    let dir = cache_dir
+        .join("tls")
+        .join(&sha256_hex(names.join(",").as_bytes())[..16]);
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    let certified = rcgen::generate_simple_self_signed(names.clone())
+        .map_err(|e| format!("Failed to generate certificate: {}", e))?;
+
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    tokio::fs::write(&cert_path, certified.cert.pem())
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", cert_path.display(), e))?;
+    tokio::fs::write(&key_path, certified.key_pair.serialize_pem())
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", key_path.display(), e))?;
+
+    info!(target: "api",
+        "Generated self-signed certificate for {} at {}, trust it on the clients to get a secure context",
+        names.join(", "),
+        cert_path.display()
+    );
+
+    Ok((cert_path, key_path))
+}
+
+async fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let pem = tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem.as_slice()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", path.display()));
+    }
+
+    Ok(certs)
+}
+
+async fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let pem = tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    rustls_pemfile::private_key(&mut BufReader::new(pem.as_slice()))
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("No private key found in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_rustls::{
+        TlsConnector,
+        rustls::{ClientConfig, RootCertStore, pki_types::ServerName},
+    };
+
+    #[tokio::test]
+    async fn test_stalled_handshake_does_not_block_accept() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("deno-plc-build-tls-{}", std::process::id()));
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut listener = TlsListener::new(tcp, &TlsConfig::default(), Path::new("."), &cache_dir)
+            .await
+            .unwrap();
+        let addr = listener.local_addr;
+
+        // connects, but never sends a ClientHello
+        let _stalled = TcpStream::connect(addr).await.unwrap();
+
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(
+            read_certs(listener.self_signed_cert().unwrap())
+                .await
+                .unwrap(),
+        );
+        let client_config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        let client = tokio::spawn(async move {
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            connector
+                .connect(ServerName::try_from("localhost").unwrap(), tcp)
+                .await
+                .unwrap()
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            axum::serve::Listener::accept(&mut listener),
+        )
+        .await
+        .expect("accept waited for the stalled handshake");
+        client.await.unwrap();
+
+        std::fs::remove_dir_all(&cache_dir).ok();
+    }
+}
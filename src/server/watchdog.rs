@@ -0,0 +1,63 @@
+//! Exits the graph server together with the process that launched it.
+
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+#[cfg(not(unix))]
+use tracing::warn;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Resolves once the launcher is gone: the parent `parent_pid` exited or stdin was closed.
+/// Never resolves if neither is watched.
+pub async fn launcher_exited(parent_pid: Option<u32>, watch_stdin: bool) {
+    let parent = async {
+        match parent_pid {
+            Some(pid) => parent_exited(pid).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let stdin = async {
+        if watch_stdin {
+            stdin_closed().await
+        } else {
+            std::future::pending().await
+        }
+    };
+
+    tokio::select! {
+        _ = parent => {},
+        _ = stdin => {},
+    }
+}
+
+/// Orphaned processes are reparented (to init or a subreaper), so the parent pid changes
+#[cfg(unix)]
+async fn parent_exited(pid: u32) {
+    loop {
+        if std::os::unix::process::parent_id() != pid {
+            return;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(not(unix))]
+async fn parent_exited(_pid: u32) {
+    warn!(target: "api", "Watching the parent process is not supported on this platform, use stdin instead");
+    std::future::pending().await
+}
+
+/// The launcher keeps stdin open without writing to it, EOF means it exited
+async fn stdin_closed() {
+    let mut stdin = tokio::io::stdin();
+    let mut buf = [0; 64];
+
+    loop {
+        match stdin.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
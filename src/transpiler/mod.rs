@@ -1,41 +1,218 @@
-use std::sync::LazyLock;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{self, AtomicU64, AtomicUsize},
+    },
+    time::Instant,
+};
 
 use static_assertions::assert_impl_all;
 use threadpool::ThreadPool;
-use tokio::sync::oneshot::{Receiver, channel};
+use tokio::sync::{
+    OwnedSemaphorePermit, Semaphore,
+    oneshot::{Receiver, channel},
+};
+use tracing::{debug, debug_span};
 use transform::{TransformOptions, TransformResult, transform_code};
 
+use crate::{config::Config, metrics::metrics};
+
 pub mod transform;
 
+/// Transforms that may be queued or running before `schedule` waits
+pub const DEFAULT_QUEUE_BOUND: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Warm-up of the cache
+    Background,
+    Normal,
+    /// Entry modules and their direct imports, the browser can't continue without them
+    High,
+}
+
+/// Clones share the same worker threads and queue
+#[derive(Clone)]
 pub struct TransformPool {
     pool: ThreadPool,
+    queue: Arc<Mutex<BinaryHeap<Job>>>,
+    /// Bounds the transforms submitted with `schedule`
+    slots: Arc<Semaphore>,
+    /// Callers of `schedule` waiting for a slot
+    waiting: Arc<AtomicUsize>,
+    sequence: Arc<AtomicU64>,
 }
 
 assert_impl_all!(TransformPool: Send, Sync);
 
-static TRANSFORM_POOL: LazyLock<TransformPool> = LazyLock::new(TransformPool::new);
+static TRANSFORM_POOL: LazyLock<TransformPool> =
+    LazyLock::new(|| TransformPool::new(None, DEFAULT_QUEUE_BOUND));
+
+/// Higher priority first, FIFO within the same priority
+struct Job {
+    priority: Priority,
+    sequence: Reverse<u64>,
+    run: Box<dyn FnOnce() + Send>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
 
 impl TransformPool {
-    fn new() -> Self {
-        let pool = threadpool::Builder::new()
-            .thread_name("swc_code_transform".into())
-            .build();
-        Self { pool }
+    /// `threads` defaults to the number of CPUs
+    pub fn new(threads: Option<usize>, queue_bound: usize) -> Self {
+        let mut builder = threadpool::Builder::new().thread_name("swc_code_transform".into());
+        if let Some(threads) = threads {
+            builder = builder.num_threads(threads.max(1));
+        }
+
+        Self {
+            pool: builder.build(),
+            queue: Default::default(),
+            slots: Arc::new(Semaphore::new(queue_bound.max(1))),
+            waiting: Default::default(),
+            sequence: Default::default(),
+        }
+    }
+
+    /// The shared pool, unless the config changes its size or queue bound
+    pub fn from_config(config: &Config) -> Self {
+        if config.transform_threads.is_none() && config.transform_queue.is_none() {
+            return Self::get().clone();
+        }
+
+        Self::new(
+            config.transform_threads,
+            config.transform_queue.unwrap_or(DEFAULT_QUEUE_BOUND),
+        )
     }
 
     pub fn get() -> &'static Self {
-        &*TRANSFORM_POOL
+        &TRANSFORM_POOL
     }
 
+    /// Queues the transform without waiting for a free slot, for batch jobs that submit the whole graph
     pub fn transform(&self, options: TransformOptions) -> TransformTask {
+        self.submit(options, Priority::Normal, None)
+    }
+
+    /// Waits until the queue has room, so a cold page load can't flood the pool
+    pub async fn schedule(&self, options: TransformOptions, priority: Priority) -> TransformTask {
+        let waiting = Waiting::new(&self.waiting);
+        let permit = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("transform queue closed");
+        drop(waiting);
+
+        self.submit(options, priority, Some(permit))
+    }
+
+    fn submit(
+        &self,
+        options: TransformOptions,
+        priority: Priority,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> TransformTask {
         let (tx, rx) = channel();
-        self.pool.execute(|| {
+        // the span is entered on the worker thread, it is a child of the caller's span
+        let span =
+            debug_span!(target: "transpiler", "transform", module = %options.module.specifier());
+        let queued = Instant::now();
+
+        let run = move || {
+            let _span = span.enter();
+            let waited = queued.elapsed();
+            metrics().transform_queue.observe(waited);
+
+            let start = Instant::now();
             let result = transform_code(options);
-            tx.send(Some(result)).unwrap();
+            let elapsed = start.elapsed();
+            metrics().transform.observe(elapsed);
+
+            debug!(target: "transpiler",
+                queued_ms = waited.as_secs_f64() * 1000.0,
+                transform_ms = elapsed.as_secs_f64() * 1000.0,
+                "Transformed module"
+            );
+
+            drop(permit);
+            // the receiver is gone if the request was cancelled
+            tx.send(Some(result)).ok();
+        };
+
+        self.queue.lock().unwrap().push(Job {
+            priority,
+            sequence: Reverse(self.sequence.fetch_add(1, atomic::Ordering::Relaxed)),
+            run: Box::new(run),
+        });
+
+        // every pool job runs the most important queued job, not necessarily the one pushed above
+        let queue = self.queue.clone();
+        self.pool.execute(move || {
+            let job = queue.lock().unwrap().pop();
+            if let Some(job) = job {
+                (job.run)();
+            }
         });
 
         TransformTask { chan: rx }
     }
+
+    /// Transforms waiting for a worker thread
+    pub fn queued_count(&self) -> usize {
+        self.pool.queued_count()
+    }
+
+    /// Transforms that are not queued yet because the queue is full
+    pub fn waiting_count(&self) -> usize {
+        self.waiting.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn active_count(&self) -> usize {
+        self.pool.active_count()
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.max_count()
+    }
+}
+
+/// Counts a caller of `schedule` until it got a slot or was cancelled
+struct Waiting(Arc<AtomicUsize>);
+
+impl Waiting {
+    fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, atomic::Ordering::Relaxed);
+        Self(count.clone())
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, atomic::Ordering::Relaxed);
+    }
 }
 
 pub struct TransformTask {
@@ -46,6 +223,75 @@ assert_impl_all!(TransformTask: Send);
 
 impl TransformTask {
     pub async fn result(self) -> Option<TransformResult> {
-        self.chan.await.unwrap()
+        // the sender is dropped if the transform panicked
+        self.chan.await.unwrap_or(None)
     }
 }
+
+#[test]
+fn test_job_order() {
+    let job = |priority, sequence| Job {
+        priority,
+        sequence: Reverse(sequence),
+        run: Box::new(|| {}),
+    };
+
+    let mut queue = BinaryHeap::from([
+        job(Priority::Normal, 0),
+        job(Priority::Normal, 1),
+        job(Priority::High, 2),
+        job(Priority::High, 3),
+        job(Priority::Background, 4),
+    ]);
+
+    let order = std::iter::from_fn(|| queue.pop())
+        .map(|job| job.sequence.0)
+        .collect::<Vec<_>>();
+
+    assert_eq!(order, vec![2, 3, 0, 1, 4]);
+}
+
+#[tokio::test]
+async fn test_waiting_count() {
+    use std::collections::HashMap;
+
+    use crate::{
+        config::{SourceMapOptions, Target},
+        graph::{ESMGraphModule, ModuleGraph},
+        specifier::ModuleSpecifier,
+        transpiler::transform::ModuleUrls,
+    };
+
+    let options = || TransformOptions {
+        code: "export const a = 1;".to_string(),
+        hmr: false,
+        graph: Arc::new(ModuleGraph::new()),
+        module: ESMGraphModule::detached(
+            ModuleSpecifier::parse("file:///mod.ts").unwrap(),
+            "/mod.ts".into(),
+            HashMap::new(),
+        ),
+        source_map: SourceMapOptions::default(),
+        target: Target::EsNext,
+        urls: ModuleUrls::DevServer,
+    };
+
+    let pool = TransformPool::new(Some(1), 1);
+    let permit = pool.slots.clone().acquire_owned().await.unwrap();
+
+    let scheduled = tokio::spawn({
+        let pool = pool.clone();
+        async move {
+            pool.schedule(options(), Priority::High)
+                .await
+                .result()
+                .await
+        }
+    });
+    tokio::task::yield_now().await;
+    assert_eq!(pool.waiting_count(), 1);
+
+    drop(permit);
+    assert!(scheduled.await.unwrap().is_some());
+    assert_eq!(pool.waiting_count(), 0);
+}
@@ -1,28 +1,40 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use path_clean::PathClean;
 use serde::Serialize;
 use static_assertions::assert_impl_all;
 use swc_core::atoms::Atom;
+use swc_core::base::sourcemap;
 use swc_core::common::comments::{Comment, CommentKind, Comments, SingleThreadedComments};
+use swc_core::common::source_map::SourceMapGenConfig;
 use swc_core::common::sync::Lrc;
 use swc_core::common::{BytePos, DUMMY_SP, FileName, GLOBALS, Mark, SourceMap, Spanned};
-use swc_core::ecma::ast::{ModuleDecl, Str};
+use swc_core::ecma::ast::{Module, ModuleDecl, ModuleItem, Program, Str};
 use swc_core::ecma::codegen::Emitter;
 use swc_core::ecma::codegen::text_writer::JsWriter;
 use swc_core::ecma::parser::lexer::Lexer;
 use swc_core::ecma::parser::{Parser, StringInput, Syntax, TsSyntax};
+use swc_core::ecma::preset_env::{Config as PresetEnvConfig, Targets, preset_env};
+use swc_core::ecma::transforms::base::assumptions::Assumptions;
+use swc_core::ecma::transforms::base::feature::FeatureFlag;
 use swc_core::ecma::transforms::base::fixer::fixer;
+use swc_core::ecma::transforms::base::helpers::{HELPERS, Helpers, inject_helpers};
 use swc_core::ecma::transforms::base::{hygiene::hygiene, resolver};
-use swc_core::ecma::transforms::react::{Options, RefreshOptions, Runtime, react};
+use swc_core::ecma::transforms::react::{Options, RefreshOptions, Runtime, react, refresh};
 use swc_core::ecma::transforms::typescript::strip;
 use swc_core::ecma::visit::{Fold, FoldWith};
 use swc_prefresh::PrefreshPluginConfig;
+use tracing::debug;
 use urlencoding::encode;
 
+use crate::config::{SourceMapMode, SourceMapOptions, SourceMapSources, Target};
+use crate::deno::lockfile::sha256_hex;
 use crate::graph::{ESMGraphModule, GraphModule, ModuleGraph};
+use crate::html::HMR_CLIENT;
+use crate::jsr::id::JsrPackageId;
 use crate::specifier::ModuleSpecifier;
+use crate::vendor::remote_path;
 
 fn safe_strip_prefix(path: &PathBuf, base: &PathBuf) -> Option<PathBuf> {
     let clean_path = path.clean();
@@ -47,10 +59,221 @@ fn safe_strip_prefix(path: &PathBuf, base: &PathBuf) -> Option<PathBuf> {
 //     assert_eq!(result, Some(PathBuf::from("frontend\\app\\App.tsx")));
 // }
 
+/// URL the dev server serves `module` at
+pub fn module_url(graph: &ModuleGraph, module: &GraphModule) -> String {
+    match module {
+        GraphModule::Esm(module) => {
+            let specifier: Arc<ModuleSpecifier> = module.specifier();
+
+            match specifier.scheme() {
+                "file" => {
+                    if let Ok(path) = specifier.to_file_path() {
+                        if let Some(relative) = safe_strip_prefix(&path, &graph.root_dir) {
+                            format!("/{}", relative.to_string_lossy().replace("\\", "/"))
+                        } else if let Some((member, relative)) = graph.workspace.member_of(&path) {
+                            format!(
+                                "{}/{}",
+                                member.prefix,
+                                relative.to_string_lossy().replace("\\", "/")
+                            )
+                        } else {
+                            format!(
+                                "/@module/{}",
+                                encode(&specifier.to_string().replace("\\", "/"))
+                            )
+                        }
+                    } else {
+                        "/@module/error/invalid-file-url".to_string()
+                    }
+                }
+                "http" | "https" => {
+                    if JsrPackageId::from_url(&specifier).is_some() {
+                        format!("/@jsr{}", specifier.path())
+                    } else {
+                        format!("/@module/{}", encode(specifier.as_str()))
+                    }
+                }
+                _ => format!(
+                    "/@module/error/{}",
+                    encode("Unsupported scheme for ESM Import")
+                ),
+            }
+        }
+        GraphModule::Npm(module) => {
+            let package = module.package();
+            let subpath = module.subpath();
+            if subpath.is_empty() {
+                format!(
+                    "/@npm/{}/{}",
+                    encode(&package.id().name),
+                    package.id().version,
+                )
+            } else {
+                format!(
+                    "/@npm/{}/{}/{}",
+                    encode(&package.id().name),
+                    package.id().version,
+                    encode(&subpath)
+                )
+            }
+        }
+        GraphModule::Virtual(module) => {
+            format!(
+                "/@module/{}",
+                encode(&module.specifier().to_string().replace("\\", "/"))
+            )
+        }
+    }
+}
+
+/// Where the imports of transformed modules point to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModuleUrls {
+    /// Routes of the dev server (`module_url`)
+    #[default]
+    DevServer,
+    /// Files written by the `html` command (`static_path`), npm packages are loaded from esm.sh
+    Static,
+}
+
+impl ModuleUrls {
+    pub fn url(self, graph: &ModuleGraph, module: &GraphModule) -> String {
+        match self {
+            ModuleUrls::DevServer => module_url(graph, module),
+            ModuleUrls::Static => static_url(graph, module),
+        }
+    }
+}
+
+/// Path segments (below the output directory) of the file the `html` command writes the module to.
+/// The files are JavaScript whatever the source was, `.js` is appended to other extensions.
+pub fn static_path(graph: &ModuleGraph, module: &GraphModule) -> Option<Vec<String>> {
+    let GraphModule::Esm(module) = module else {
+        return None;
+    };
+    let specifier = module.specifier();
+
+    let path_segments = |path: &Path| {
+        path.components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+    };
+    let hashed = || {
+        vec![
+            "@module".to_string(),
+            sha256_hex(specifier.as_str().as_bytes()),
+        ]
+    };
+
+    let mut segments = match specifier.scheme() {
+        "file" => {
+            let path = specifier.to_file_path().ok()?;
+            if let Some(relative) = safe_strip_prefix(&path, &graph.root_dir) {
+                path_segments(&relative)
+            } else if let Some((member, relative)) = graph.workspace.member_of(&path) {
+                let mut segments = vec!["@workspace".to_string(), member.name.replace('/', "+")];
+                segments.extend(path_segments(&relative.clean()));
+                segments
+            } else {
+                hashed()
+            }
+        }
+        "http" | "https" if JsrPackageId::from_url(&specifier).is_some() => {
+            let mut segments = vec!["@jsr".to_string()];
+            for segment in specifier.path_segments()? {
+                segments.push(urlencoding::decode(segment).ok()?.into_owned());
+            }
+            segments
+        }
+        _ => hashed(),
+    };
+
+    let file_name = segments.last_mut()?;
+    if !file_name.ends_with(".js") && !file_name.ends_with(".mjs") {
+        file_name.push_str(".js");
+    }
+
+    // never leaves the output directory
+    if segments
+        .iter()
+        .any(|segment| matches!(segment.as_str(), "" | "." | "..") || segment.contains(['/', '\\']))
+    {
+        return None;
+    }
+
+    Some(segments)
+}
+
+fn static_url(graph: &ModuleGraph, module: &GraphModule) -> String {
+    match module {
+        GraphModule::Esm(esm) => match static_path(graph, module) {
+            Some(segments) => format!(
+                "/{}",
+                segments
+                    .iter()
+                    .map(|segment| encode(segment))
+                    .collect::<Vec<_>>()
+                    .join("/")
+            ),
+            None => esm.specifier().to_string(),
+        },
+        GraphModule::Npm(module) => {
+            let package = module.package();
+            let subpath = module.subpath();
+            if subpath.is_empty() {
+                format!(
+                    "https://esm.sh/{}@{}",
+                    package.id().name,
+                    package.id().version
+                )
+            } else {
+                format!(
+                    "https://esm.sh/{}@{}/{}",
+                    package.id().name,
+                    package.id().version,
+                    subpath
+                )
+            }
+        }
+        GraphModule::Virtual(module) => module.specifier().to_string(),
+    }
+}
+
+/// Prepended to modules transformed with `hmr`, the client (src/hmr/mod.ts) keeps the module records.
+/// `__hmr_register__` and `__hmr_signature__` are called by the refresh transform.
+const HMR_PROLOGUE: &str = r#"
+import { createHotContext as __hmr_context__ } from "HMR_CLIENT";
+import.meta.hot = __hmr_context__(import.meta.url);
+const __hmr_register__ = (type, id) => import.meta.hot.register(type, id);
+const __hmr_signature__ = () => import.meta.hot.signature();
+"#;
+
+fn hmr_prologue() -> Vec<ModuleItem> {
+    let source_map = SourceMap::default();
+    let source_file = source_map.new_source_file(
+        Lrc::new(FileName::Anon),
+        HMR_PROLOGUE.replace("HMR_CLIENT", HMR_CLIENT),
+    );
+
+    let lexer = Lexer::new(
+        Syntax::Es(Default::default()),
+        Default::default(),
+        StringInput::from(&*source_file),
+        None,
+    );
+
+    Parser::new_from(lexer)
+        .parse_module()
+        .expect("Invalid HMR prologue")
+        .body
+}
+
 struct ImportResolver {
     graph: Arc<ModuleGraph>,
     module: Arc<ESMGraphModule>,
-    comments: Arc<SingleThreadedComments>,
+    urls: ModuleUrls,
+    /// Shares the comments of the transform (`SingleThreadedComments` is a handle)
+    comments: SingleThreadedComments,
     // root_dir: Arc<PathBuf>,
 }
 
@@ -61,7 +284,7 @@ impl ImportResolver {
         let mut resolved = self.module.lookup_import(import_path);
 
         if resolved.is_none() {
-            println!(
+            debug!(target: "transpiler",
                 "Trying to resolve import {} with global packages",
                 import_path
             );
@@ -70,93 +293,12 @@ impl ImportResolver {
 
         let import_comment: Vec<String> = vec![format!(" import \"{}\";", import_path)];
 
-        let import_string = if let Some(resolved) = resolved {
-            match resolved {
-                GraphModule::Esm(module) => {
-                    let specifier: Arc<ModuleSpecifier> = module.specifier();
-
-                    match specifier.scheme() {
-                        "file" => {
-                            if let Ok(path) = specifier.to_file_path() {
-                                if let Some(relative) =
-                                    safe_strip_prefix(&path, &self.graph.root_dir)
-                                {
-                                    format!("/{}", relative.to_string_lossy().replace("\\", "/"))
-                                } else {
-                                    format!(
-                                        "/@module/{}",
-                                        encode(&specifier.to_string().replace("\\", "/"))
-                                    )
-                                }
-                            } else {
-                                "/@module/error/invalid-file-url".to_string()
-                            }
-                        }
-                        "http" | "https" => {
-                            format!("/@module/{}", encode(specifier.as_str()))
-                        }
-                        _ => format!(
-                            "/@module/error/{}",
-                            encode("Unsupported scheme for ESM Import")
-                        ),
-                    }
-                }
-                GraphModule::Npm(module) => {
-                    let package = module.package();
-                    let subpath = module.subpath();
-                    if subpath.is_empty() {
-                        format!(
-                            "/@npm/{}/{}",
-                            encode(&package.id().name),
-                            package.id().version,
-                        )
-                    } else {
-                        format!(
-                            "/@npm/{}/{}/{}",
-                            encode(&package.id().name),
-                            package.id().version,
-                            encode(&subpath)
-                        )
-                    }
-                }
-                GraphModule::Virtual(module) => {
-                    format!(
-                        "/@module/{}",
-                        encode(&module.specifier().to_string().replace("\\", "/"))
-                    )
-                }
-            }
-
-            // let specifier: Arc<ModuleSpecifier> = resolved.specifier();
-
-            // match specifier.scheme() {
-            //     "file" => {
-            //         if let Ok(path) = specifier.to_file_path() {
-            //             if let Some(relative) = safe_strip_prefix(&path, &self.graph.root_dir) {
-            //                 format!("/{}", relative.to_string_lossy().replace("\\", "/"))
-            //             } else {
-            //                 format!(
-            //                     "/@module/{}",
-            //                     encode(&specifier.to_string().replace("\\", "/"))
-            //                 )
-            //             }
-            //         } else {
-            //             "/@module/error/invalid-file-url".to_string()
-            //         }
-            //     }
-            //     "http" | "https" => {
-            //         format!("/@module/{}", encode(specifier.as_str()))
-            //     }
-            //     "npm" => {
-            //         format!("/@npm/{}", encode(&specifier.path()[1..]))
-            //     }
-            //     _ => "/@module/error/unsupported-scheme".to_string(),
-            // }
-        } else {
-            format!(
+        let import_string = match resolved {
+            Some(resolved) => self.urls.url(&self.graph, &resolved),
+            None => format!(
                 "/@module/error/{}",
                 encode(&format!("Failed to resolve import {}", import_path))
-            )
+            ),
         };
 
         src.raw = None;
@@ -195,7 +337,7 @@ impl Fold for ImportResolver {
             | ModuleDecl::ExportDefaultDecl(_)
             | ModuleDecl::ExportDefaultExpr(_) => {}
             rem => {
-                println!("Module Decl: {:?}, Ctx: {}", rem, self.module.specifier());
+                debug!(target: "transpiler", "Module Decl: {:?}, Ctx: {}", rem, self.module.specifier());
             }
         }
 
@@ -209,28 +351,170 @@ pub struct TransformOptions {
     pub hmr: bool,
     pub graph: Arc<ModuleGraph>,
     pub module: Arc<ESMGraphModule>,
+    pub source_map: SourceMapOptions,
+    pub target: Target,
+    pub urls: ModuleUrls,
     // pub root_dir: Arc<PathBuf>,
 }
 
 assert_impl_all!(TransformOptions: Send, Sync);
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TransformResult {
     pub code: String,
-    pub source_map: String,
+    /// Source map JSON, only with `SourceMapMode::External` (the inline map is part of `code`)
+    pub source_map: Option<String>,
 }
 
 assert_impl_all!(TransformResult: Send, Sync);
 
+/// Generates `sources` (and `sourcesContent`) of the source map
+struct SourceMapConfig {
+    options: SourceMapOptions,
+    root_dir: PathBuf,
+}
+
+impl SourceMapGenConfig for SourceMapConfig {
+    fn file_name_to_source(&self, f: &FileName) -> String {
+        let FileName::Url(url) = f else {
+            return f.to_string();
+        };
+
+        // root-relative, a plain relative path would be resolved against the URL of the map
+        if self.options.sources == SourceMapSources::Relative
+            && let Some(relative) = url
+                .to_file_path()
+                .ok()
+                .and_then(|path| safe_strip_prefix(&path, &self.root_dir))
+        {
+            return format!("/{}", relative.to_string_lossy().replace("\\", "/"));
+        }
+
+        url.to_string()
+    }
+
+    fn inline_sources_content(&self, _f: &FileName) -> bool {
+        self.options.sources_content
+    }
+}
+
+/// Splits off a trailing `//# sourceMappingURL=` comment of already compiled code (ex. npm packages) and loads
+/// the referenced map (data URL, a file next to a local module or the cached copy of a remote one), the new
+/// map is chained onto it
+fn split_input_source_map(
+    code: String,
+    module: &ESMGraphModule,
+) -> (String, Option<sourcemap::SourceMap>) {
+    let Some((stripped, reference)) = code.trim_end().rsplit_once("//# sourceMappingURL=") else {
+        return (code, None);
+    };
+    if reference.contains('\n') {
+        // not the last line
+        return (code, None);
+    }
+    let stripped = stripped.to_string();
+    let specifier = module.specifier();
+
+    // the `sources` of the map are relative to it
+    let (decoded, map_url) = if reference.starts_with("data:") {
+        (
+            sourcemap::decode_data_url(reference).ok(),
+            Some(specifier.as_ref().clone()),
+        )
+    } else if specifier.scheme() == "file" {
+        let decoded = module
+            .local()
+            .parent()
+            .map(|dir| dir.join(reference))
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|map| sourcemap::decode_slice(&map).ok());
+        (decoded, None)
+    } else {
+        let map_url = specifier.join(reference).ok();
+        let decoded = map_url
+            .as_ref()
+            .and_then(|url| remote_sibling(&specifier, module.local(), url))
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|map| sourcemap::decode_slice(&map).ok());
+        (decoded, map_url)
+    };
+
+    let map = match decoded {
+        Some(sourcemap::DecodedMap::Regular(mut map)) => {
+            // remote sources are served by URL, relative ones would resolve against the dev server
+            if let Some(map_url) = map_url.filter(|_| specifier.scheme() != "file") {
+                for index in 0..map.get_source_count() {
+                    let resolved = map
+                        .get_source(index)
+                        .and_then(|source| map_url.join(source).ok());
+                    if let Some(resolved) = resolved {
+                        map.set_source(index, resolved.as_str());
+                    }
+                }
+            }
+            Some(map)
+        }
+        Some(_) => {
+            debug!(target: "transpiler",
+                "Unsupported input source map of {}, not chained",
+                specifier
+            );
+            None
+        }
+        None => None,
+    };
+
+    (stripped, map)
+}
+
+/// Local copy of `url`, a file on the same server as the remote `module` (at `local`). Deno cache: the file
+/// names are hashes of the URL path and query, vendored: the URL path below `remote/<host>`.
+/// Deno doesn't download source maps, they are only found if cached or vendored alongside.
+fn remote_sibling(
+    module: &ModuleSpecifier,
+    local: &Path,
+    url: &ModuleSpecifier,
+) -> Option<PathBuf> {
+    if module.origin() != url.origin() {
+        return None;
+    }
+
+    let cache_name = |specifier: &ModuleSpecifier| {
+        let mut rest = specifier.path().to_string();
+        if let Some(query) = specifier.query() {
+            rest.push('?');
+            rest.push_str(query);
+        }
+        sha256_hex(rest.as_bytes())
+    };
+
+    if local.file_name()? == cache_name(module).as_str() {
+        return Some(local.with_file_name(cache_name(url)));
+    }
+
+    let vendored = PathBuf::from(remote_path(module));
+    if local.ends_with(&vendored) {
+        let vendor_dir = local.ancestors().nth(vendored.components().count())?;
+        return Some(vendor_dir.join(remote_path(url)));
+    }
+
+    None
+}
+
 pub fn transform_code(options: TransformOptions) -> TransformResult {
     let source_map = Arc::new(SourceMap::default());
 
+    let (code, input_map) = match options.source_map.mode {
+        SourceMapMode::None => (options.code, None),
+        _ => split_input_source_map(options.code, &options.module),
+    };
+
     let source_file = source_map.new_source_file(
         Lrc::new(FileName::Url(options.module.specifier().as_ref().clone())),
-        options.code,
+        code,
     );
 
-    let comments = Arc::new(SingleThreadedComments::default());
+    let comments = SingleThreadedComments::default();
 
     let lexer = Lexer::new(
         Syntax::Typescript(TsSyntax {
@@ -259,18 +543,26 @@ pub fn transform_code(options: TransformOptions) -> TransformResult {
 
         program = program.apply(strip(unresolved_mark, top_level_mark));
 
+        // components are registered with the HMR client. Not part of `react`, `development` would import
+        // `preact/jsx-dev-runtime`, which is not in the graph
+        program = program.apply(refresh(
+            options.hmr,
+            Some(RefreshOptions {
+                refresh_reg: "__hmr_register__".into(),
+                refresh_sig: "__hmr_signature__".into(),
+                ..Default::default()
+            }),
+            source_map.clone(),
+            Some(&comments),
+            top_level_mark,
+        ));
+
         program = program.apply(react(
             source_map.clone(),
             Some(&comments),
             Options {
                 import_source: Some("preact".into()),
                 runtime: Some(Runtime::Automatic),
-                development: Some(options.hmr),
-                refresh: if options.hmr {
-                    Some(RefreshOptions::default())
-                } else {
-                    None
-                },
                 ..Default::default()
             },
             top_level_mark,
@@ -296,12 +588,53 @@ pub fn transform_code(options: TransformOptions) -> TransformResult {
             // dbg!(&program);
         }
 
+        if let Some(query) = options.target.browserslist() {
+            // `Query` isn't exported by this swc version, the untagged `Targets` accepts the plain query
+            let targets: Targets = serde_json::from_value(serde_json::Value::String(query))
+                .expect("Invalid browserslist query");
+
+            // helpers of the lowered syntax are inlined into the module
+            program = HELPERS.set(&Helpers::new(false), || {
+                program
+                    .apply(preset_env(
+                        unresolved_mark,
+                        Some(&comments),
+                        PresetEnvConfig {
+                            targets: Some(targets),
+                            ..Default::default()
+                        },
+                        Assumptions::default(),
+                        &mut FeatureFlag::empty(),
+                    ))
+                    .apply(inject_helpers(unresolved_mark))
+            });
+        }
+
         program = program.fold_with(&mut ImportResolver {
             module: options.module.clone(),
             graph: options.graph.clone(),
+            urls: options.urls,
             comments: comments.clone(),
         });
 
+        // after resolving, the client is not part of the graph
+        if options.hmr {
+            program = match program {
+                Program::Module(mut module) => {
+                    module.body.splice(0..0, hmr_prologue());
+                    Program::Module(module)
+                }
+                Program::Script(script) => Program::Module(Module {
+                    span: script.span,
+                    body: hmr_prologue()
+                        .into_iter()
+                        .chain(script.body.into_iter().map(ModuleItem::Stmt))
+                        .collect(),
+                    shebang: script.shebang,
+                }),
+            };
+        }
+
         program = program.apply(hygiene());
 
         program = program.apply(fixer(Some(&comments)));
@@ -323,26 +656,216 @@ pub fn transform_code(options: TransformOptions) -> TransformResult {
         .emit_program(&program)
         .expect("Failed to emit module");
 
-        let sm = source_map
-            .build_source_map(&output_mapping)
-            .to_data_url()
-            .unwrap();
+        let map = (options.source_map.mode != SourceMapMode::None).then(|| {
+            source_map.build_source_map_with_config(
+                &output_mapping,
+                input_map.as_ref(),
+                SourceMapConfig {
+                    options: options.source_map,
+                    root_dir: options.graph.root_dir.clone(),
+                },
+            )
+        });
 
-        output_buffer.extend_from_slice(b"\n//# sourceMappingURL=");
-        output_buffer.extend_from_slice(sm.as_bytes());
+        let mut external_map = None;
+
+        match (options.source_map.mode, map) {
+            (SourceMapMode::Inline, Some(map)) => {
+                output_buffer.extend_from_slice(b"\n//# sourceMappingURL=");
+                output_buffer.extend_from_slice(map.to_data_url().unwrap().as_bytes());
+            }
+            (SourceMapMode::External, Some(map)) => {
+                let url = options
+                    .urls
+                    .url(&options.graph, &GraphModule::Esm(options.module.clone()));
+                output_buffer.extend_from_slice(b"\n//# sourceMappingURL=");
+                output_buffer.extend_from_slice(url.as_bytes());
+                output_buffer.extend_from_slice(b".map");
+
+                let mut json = Vec::new();
+                map.to_writer(&mut json).unwrap();
+                external_map = Some(String::from_utf8(json).expect("Invalid UTF-8"));
+            }
+            _ => {}
+        }
 
         let modified_code = String::from_utf8(output_buffer).expect("Invalid UTF-8");
 
         result = Some(TransformResult {
             code: modified_code,
-            source_map: sm,
+            source_map: external_map,
         });
     });
 
     result.unwrap()
 }
 
+/// Static import and re-export specifiers of `code`, for modules that are not analyzed by deno
+/// (inline scripts of HTML entrypoints)
+pub fn collect_imports(code: &str) -> Result<Vec<String>, String> {
+    let source_map = SourceMap::default();
+    let source_file = source_map.new_source_file(Lrc::new(FileName::Anon), code.to_string());
+
+    let lexer = Lexer::new(
+        Syntax::Typescript(TsSyntax {
+            tsx: true,
+            ..Default::default()
+        }),
+        Default::default(),
+        StringInput::from(&*source_file),
+        None,
+    );
+
+    let module = Parser::new_from(lexer)
+        .parse_module()
+        .map_err(|e| e.kind().msg().to_string())?;
+
+    Ok(module
+        .body
+        .iter()
+        .filter_map(|item| match item {
+            ModuleItem::ModuleDecl(ModuleDecl::Import(import)) => Some(&import.src),
+            ModuleItem::ModuleDecl(ModuleDecl::ExportAll(export)) => Some(&export.src),
+            ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(export)) => export.src.as_ref(),
+            _ => None,
+        })
+        .map(|src| src.value.to_string())
+        .collect())
+}
+
 // #[test]
 // fn test() {
 // assert!(!PathBuf::from("/foo/bar/../baz").starts_with("/foo/bar"));
 // }
+
+#[test]
+fn test_chains_cached_input_source_map() {
+    use std::collections::HashMap;
+
+    let deno_cache =
+        std::env::temp_dir().join(format!("deno-plc-build-source-map-{}", std::process::id()));
+    let dir = deno_cache.join("remote/https/example.com");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let specifier = ModuleSpecifier::parse("https://example.com/dist/mod.js").unwrap();
+    let local = dir.join(sha256_hex(b"/dist/mod.js"));
+    std::fs::write(
+        dir.join(sha256_hex(b"/dist/mod.js.map")),
+        r#"{"version":3,"sources":["../src/mod.ts"],"names":[],"mappings":"AAAA"}"#,
+    )
+    .unwrap();
+
+    let result = transform_code(TransformOptions {
+        code: "export const a = 1;\n//# sourceMappingURL=mod.js.map\n".to_string(),
+        hmr: false,
+        graph: Arc::new(ModuleGraph::new()),
+        module: ESMGraphModule::detached(specifier, local, HashMap::new()),
+        source_map: SourceMapOptions {
+            mode: SourceMapMode::External,
+            ..Default::default()
+        },
+        target: Target::EsNext,
+        urls: ModuleUrls::DevServer,
+    });
+
+    let map = sourcemap::SourceMap::from_slice(result.source_map.unwrap().as_bytes()).unwrap();
+    assert_eq!(
+        map.sources().collect::<Vec<_>>(),
+        vec!["https://example.com/src/mod.ts"]
+    );
+    assert!(!result.code.contains("sourceMappingURL=mod.js.map"));
+
+    std::fs::remove_dir_all(&deno_cache).ok();
+}
+
+#[test]
+fn test_relative_sources_are_root_relative() {
+    let root_dir = std::env::temp_dir().join("deno-plc-build-sources");
+    let config = SourceMapConfig {
+        options: SourceMapOptions {
+            sources: SourceMapSources::Relative,
+            ..Default::default()
+        },
+        root_dir: root_dir.clone(),
+    };
+
+    let local = url::Url::from_file_path(root_dir.join("src").join("a.ts")).unwrap();
+    assert_eq!(
+        config.file_name_to_source(&FileName::Url(local)),
+        "/src/a.ts"
+    );
+
+    let remote = url::Url::parse("https://jsr.io/@std/assert/1.0.0/mod.ts").unwrap();
+    assert_eq!(
+        config.file_name_to_source(&FileName::Url(remote.clone())),
+        remote.as_str()
+    );
+}
+
+#[test]
+fn test_hmr_registers_components() {
+    use std::collections::HashMap;
+
+    let result = transform_code(TransformOptions {
+        code: "export function App() { return <div />; }\n".to_string(),
+        hmr: true,
+        graph: Arc::new(ModuleGraph::new()),
+        module: ESMGraphModule::detached(
+            ModuleSpecifier::parse("file:///app.tsx").unwrap(),
+            "/app.tsx".into(),
+            HashMap::new(),
+        ),
+        source_map: SourceMapOptions {
+            mode: SourceMapMode::None,
+            ..Default::default()
+        },
+        target: Target::EsNext,
+        urls: ModuleUrls::DevServer,
+    });
+
+    assert!(result.code.contains(HMR_CLIENT));
+    assert!(result.code.contains("import.meta.hot = "));
+    assert!(result.code.contains("__hmr_register__(_c, \"App\")"));
+    assert!(!result.code.contains("jsx-dev-runtime"));
+}
+
+#[test]
+fn test_static_urls() {
+    use std::collections::HashMap;
+
+    let mut graph = ModuleGraph::new();
+    graph.root_dir = PathBuf::from("/project");
+
+    let module = |specifier: &str| {
+        let specifier = ModuleSpecifier::parse(specifier).unwrap();
+        GraphModule::Esm(ESMGraphModule::detached(
+            specifier,
+            "/cache".into(),
+            HashMap::new(),
+        ))
+    };
+
+    assert_eq!(
+        ModuleUrls::Static.url(&graph, &module("file:///project/src/app.tsx")),
+        "/src/app.tsx.js"
+    );
+    assert_eq!(
+        ModuleUrls::Static.url(&graph, &module("file:///project/src/util.js")),
+        "/src/util.js"
+    );
+    assert_eq!(
+        ModuleUrls::Static.url(&graph, &module("https://jsr.io/@std/path/1.0.8/mod.ts")),
+        "/%40jsr/%40std/path/1.0.8/mod.ts.js"
+    );
+    assert_eq!(
+        ModuleUrls::Static.url(&graph, &module("https://example.com/mod.js")),
+        format!(
+            "/%40module/{}.js",
+            sha256_hex(b"https://example.com/mod.js")
+        )
+    );
+    assert_eq!(
+        ModuleUrls::DevServer.url(&graph, &module("file:///project/src/app.tsx")),
+        "/src/app.tsx"
+    );
+}